@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+drop index command_mac_status_index;
+
+drop table command_queue;
@@ -0,0 +1,13 @@
+-- Your SQL goes here
+create table
+    command_queue (
+        id INTEGER not null constraint command_id_key primary key autoincrement,
+        mac TEXT not null,
+        command TEXT not null,
+        status TEXT default 'pending' not null,
+        result TEXT,
+        created_at TEXT not null,
+        updated_at TEXT not null
+    );
+
+create index command_mac_status_index on command_queue (mac, status);
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+alter table command_queue
+drop column timeline_step;
+
+drop table timeline_step;
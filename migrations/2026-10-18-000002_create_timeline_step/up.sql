@@ -0,0 +1,12 @@
+-- Your SQL goes here
+create table
+    timeline_step (
+        name TEXT not null constraint timeline_name_key primary key,
+        command TEXT not null,
+        status TEXT not null,
+        target_count INTEGER not null,
+        executed_at TEXT not null
+    );
+
+alter table command_queue
+add column timeline_step TEXT;
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+alter table id_bind
+drop column sync_error;
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+alter table id_bind
+add column sync_error TEXT;
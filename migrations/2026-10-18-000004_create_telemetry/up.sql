@@ -0,0 +1,13 @@
+-- Your SQL goes here
+create table
+    telemetry (
+        mac TEXT not null constraint telemetry_mac_key primary key,
+        caddy_active BOOLEAN not null,
+        graphical_session BOOLEAN not null,
+        uptime_secs BIGINT,
+        load_avg DOUBLE,
+        home_free_bytes BIGINT,
+        clock_offset_ms BIGINT not null,
+        hostname TEXT,
+        updated_at TEXT not null
+    );
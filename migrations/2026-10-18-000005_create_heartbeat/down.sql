@@ -0,0 +1,15 @@
+-- This file should undo anything in `up.sql`
+alter table id_bind
+drop column online;
+
+drop index seat_event_created_at_index;
+
+drop index seat_event_mac_index;
+
+drop table seat_event;
+
+drop index heartbeat_received_at_index;
+
+drop index heartbeat_mac_index;
+
+drop table heartbeat;
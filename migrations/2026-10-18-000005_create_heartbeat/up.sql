@@ -0,0 +1,27 @@
+-- Your SQL goes here
+create table
+    heartbeat (
+        id INTEGER not null constraint heartbeat_id_key primary key autoincrement,
+        mac TEXT not null,
+        ip TEXT not null,
+        received_at BIGINT not null
+    );
+
+create index heartbeat_mac_index on heartbeat (mac, received_at);
+
+create index heartbeat_received_at_index on heartbeat (received_at);
+
+create table
+    seat_event (
+        id INTEGER not null constraint seat_event_id_key primary key autoincrement,
+        mac TEXT not null,
+        event TEXT not null,
+        created_at BIGINT not null
+    );
+
+create index seat_event_mac_index on seat_event (mac, created_at);
+
+create index seat_event_created_at_index on seat_event (created_at);
+
+alter table id_bind
+add column online BOOLEAN default true not null;
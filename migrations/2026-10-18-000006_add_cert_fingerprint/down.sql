@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+drop index id_bind_cert_fingerprint_index;
+
+alter table id_bind
+drop column cert_fingerprint;
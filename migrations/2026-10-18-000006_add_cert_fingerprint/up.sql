@@ -0,0 +1,5 @@
+-- Your SQL goes here
+alter table id_bind
+add column cert_fingerprint TEXT;
+
+create unique index id_bind_cert_fingerprint_index on id_bind (cert_fingerprint);
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+drop index pending_bind_status_index;
+
+drop table pending_bind;
@@ -0,0 +1,18 @@
+-- Your SQL goes here
+create table
+    pending_bind (
+        id INTEGER not null constraint pending_bind_id_key primary key autoincrement,
+        mac TEXT not null,
+        bind_id TEXT not null,
+        ip TEXT not null,
+        client_version TEXT not null,
+        reason TEXT not null,
+        secret TEXT not null,
+        status TEXT default 'pending' not null,
+        cert_pem TEXT,
+        key_pem TEXT,
+        created_at TEXT not null,
+        decided_at TEXT
+    );
+
+create index pending_bind_status_index on pending_bind (status, mac);
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+drop index seat_position_index;
+
+drop table seat;
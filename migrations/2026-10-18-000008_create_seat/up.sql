@@ -0,0 +1,11 @@
+-- Your SQL goes here
+create table
+    seat (
+        ip TEXT not null constraint seat_ip_key primary key,
+        room TEXT not null,
+        row INTEGER not null,
+        col INTEGER not null,
+        hostname TEXT not null
+    );
+
+create unique index seat_position_index on seat (room, row, col);
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+alter table seat
+add column bind_id TEXT;
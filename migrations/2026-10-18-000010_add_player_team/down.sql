@@ -0,0 +1,9 @@
+-- This file should undo anything in `up.sql`
+alter table player
+drop column location;
+
+alter table player
+drop column organization;
+
+alter table player
+drop column team_name;
@@ -0,0 +1,9 @@
+-- Your SQL goes here
+alter table player
+add column team_name TEXT;
+
+alter table player
+add column organization TEXT;
+
+alter table player
+add column location TEXT;
@@ -0,0 +1,52 @@
+-- This file should undo anything in `up.sql`
+create table
+    player_single (
+        id TEXT not null constraint id_key primary key,
+        username TEXT not null,
+        password TEXT not null,
+        synced INTEGER default false not null,
+        team_name TEXT,
+        organization TEXT,
+        location TEXT
+    );
+
+insert into
+    player_single (
+        id,
+        username,
+        password,
+        synced,
+        team_name,
+        organization,
+        location
+    )
+select
+    id,
+    username,
+    password,
+    synced,
+    team_name,
+    organization,
+    location
+from
+    player
+where
+    contest = (
+        select
+            name
+        from
+            contest
+        where
+            active
+    );
+
+drop index player_id_index;
+
+drop table player;
+
+alter table player_single
+rename to player;
+
+create unique index id_index on player (id);
+
+drop table contest;
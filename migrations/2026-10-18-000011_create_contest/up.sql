@@ -0,0 +1,56 @@
+-- Your SQL goes here
+create table
+    contest (
+        name TEXT not null constraint contest_name_key primary key,
+        active BOOLEAN default false not null,
+        activated_at TEXT
+    );
+
+insert into
+    contest (name, active)
+values
+    ('default', true);
+
+-- SQLite cannot change a primary key in place, rebuild player keyed by contest and ID
+create table
+    player_profile (
+        contest TEXT default 'default' not null,
+        id TEXT not null,
+        username TEXT not null,
+        password TEXT not null,
+        synced INTEGER default false not null,
+        team_name TEXT,
+        organization TEXT,
+        location TEXT,
+        constraint player_key primary key (contest, id)
+    );
+
+insert into
+    player_profile (
+        contest,
+        id,
+        username,
+        password,
+        synced,
+        team_name,
+        organization,
+        location
+    )
+select
+    'default',
+    id,
+    username,
+    password,
+    synced,
+    team_name,
+    organization,
+    location
+from
+    player;
+
+drop table player;
+
+alter table player_profile
+rename to player;
+
+create index player_id_index on player (id);
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+drop index audit_event_created_at_index;
+
+drop table audit_event;
@@ -0,0 +1,14 @@
+-- Your SQL goes here
+create table
+    audit_event (
+        id INTEGER not null constraint audit_event_id_key primary key autoincrement,
+        event TEXT not null,
+        actor TEXT not null,
+        mac TEXT,
+        ip TEXT,
+        target TEXT,
+        detail TEXT not null,
+        created_at BIGINT not null
+    );
+
+create index audit_event_created_at_index on audit_event (created_at);
@@ -0,0 +1,7 @@
+-- Your SQL goes here
+create table
+    runtime_toggle (
+        name TEXT not null constraint runtime_toggle_name_key primary key,
+        enabled BOOLEAN not null,
+        updated_at TEXT
+    );
@@ -0,0 +1,11 @@
+-- This file should undo anything in `up.sql`
+alter table pending_bind
+drop column machine_fingerprint;
+
+drop index id_bind_machine_fingerprint_index;
+
+alter table id_bind
+drop column reported_fingerprint;
+
+alter table id_bind
+drop column machine_fingerprint;
@@ -0,0 +1,11 @@
+-- Your SQL goes here
+alter table id_bind
+add column machine_fingerprint TEXT;
+
+alter table id_bind
+add column reported_fingerprint TEXT;
+
+create index id_bind_machine_fingerprint_index on id_bind (machine_fingerprint);
+
+alter table pending_bind
+add column machine_fingerprint TEXT;
@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+alter table id_bind
+drop column caddy_drift_at;
+
+alter table id_bind
+drop column caddy_drift;
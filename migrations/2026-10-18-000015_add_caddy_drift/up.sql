@@ -0,0 +1,6 @@
+-- Your SQL goes here
+alter table id_bind
+add column caddy_drift TEXT;
+
+alter table id_bind
+add column caddy_drift_at TEXT;
@@ -1,27 +1,92 @@
 import * as z from "zod";
 
 
+export const TelemetrySchema = z.object({
+    "caddy_active": z.boolean(),
+    "graphical_session": z.boolean(),
+    "uptime_secs": z.union([z.null(), z.number()]),
+    "load_avg": z.union([z.null(), z.number()]),
+    "home_free_bytes": z.union([z.null(), z.number()]),
+    "clock_offset_ms": z.number(),
+    "hostname": z.union([z.null(), z.string()]),
+    "updated_at": z.string(),
+});
+export type Telemetry = z.infer<typeof TelemetrySchema>;
+
+export const SeatSchema = z.object({
+    "ip": z.string(),
+    "room": z.string(),
+    "row": z.number(),
+    "col": z.number(),
+    "hostname": z.string(),
+    "id": z.union([z.null(), z.string()]),
+});
+export type Seat = z.infer<typeof SeatSchema>;
+
 export const InfoSchema = z.object({
     "mac": z.union([z.null(), z.string()]),
     "id": z.string(),
     "ip": z.union([z.null(), z.string()]),
     "last_seen": z.union([z.null(), z.string()]),
     "username": z.union([z.null(), z.string()]),
-    "password": z.union([z.null(), z.string()]),
+    "team_name": z.union([z.null(), z.string()]),
+    "organization": z.union([z.null(), z.string()]),
+    "location": z.union([z.null(), z.string()]),
     "client_version": z.union([z.null(), z.string()]),
     "synced": z.union([z.boolean(), z.null()]),
+    "online": z.union([z.boolean(), z.null()]),
+    "offline_count": z.number(),
+    "flapping": z.boolean(),
+    "sync_error": z.union([z.null(), z.string()]),
+    "phase": z.union([z.null(), z.string()]),
+    "telemetry": z.union([z.null(), TelemetrySchema]),
+    "seat": z.union([z.null(), SeatSchema]),
+    "seat_mismatch": z.boolean(),
+    "fingerprint_mismatch": z.boolean(),
+    "caddy_drift": z.union([z.null(), z.string()]),
+    "caddy_drift_at": z.union([z.null(), z.string()]),
 });
 export type Info = z.infer<typeof InfoSchema>;
 
 export const StatusResponseSchema = z.object({
+    "contest": z.union([z.null(), z.string()]),
     "bind_count": z.number(),
     "info_count": z.number(),
     "sync_count": z.number(),
     "notsync_count": z.number(),
     "infos": z.array(InfoSchema),
+    "seats": z.array(SeatSchema),
 });
 export type StatusResponse = z.infer<typeof StatusResponseSchema>;
 
+export const StatusEventSchema = z.object({
+    "kind": z.enum(["bind", "unbind", "report", "sync", "presence", "password", "resync"]),
+    "mac": z.union([z.null(), z.string()]),
+    "info": z.union([z.null(), InfoSchema]),
+});
+export type StatusEvent = z.infer<typeof StatusEventSchema>;
+
+export const PendingBindSchema = z.object({
+    "id": z.number(),
+    "mac": z.string(),
+    "bind_id": z.string(),
+    "ip": z.string(),
+    "client_version": z.string(),
+    "reason": z.enum(["id_bound", "mac_changed", "mac_bound", "certificate_required"]),
+    "status": z.enum(["pending", "approved", "rejected"]),
+    "created_at": z.string(),
+    "decided_at": z.union([z.null(), z.string()]),
+});
+export type PendingBind = z.infer<typeof PendingBindSchema>;
+
+export const TogglesSchema = z.object({
+    "enable_bind": z.boolean(),
+    "enable_bind_update": z.boolean(),
+    "enable_sync": z.boolean(),
+    "enable_static_file": z.boolean(),
+});
+export type Toggles = z.infer<typeof TogglesSchema>;
+
 export const ErrorResponseSchema = z.object({
     "error": z.string(),
     "msg": z.string(),
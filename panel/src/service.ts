@@ -1,4 +1,5 @@
 import axios, {type AxiosInstance} from "axios";
+import type {Toggles} from "./schema.ts";
 
 const api = createBaseAPI()
 
@@ -31,4 +32,132 @@ export function removeBindByMAC(mac: string, token: string) {
             "token": token
         }
     },)
-}
\ No newline at end of file
+}
+export function queueCommand(macs: string[], command: string, token: string) {
+    return api.post("/command", {
+        "macs": macs,
+        "command": command
+    }, {
+        headers: {
+            "token": token
+        }
+    },)
+}
+
+export function getCommands(token: string, mac?: string) {
+    return api.get("/command", {
+        params: mac === undefined ? {} : {"mac": mac},
+        headers: {
+            "token": token
+        }
+    })
+}
+
+export function getHistory(mac: string, token: string) {
+    return api.get("/status/history", {
+        params: {"mac": mac},
+        headers: {
+            "token": token
+        }
+    })
+}
+
+export function getPendingBinds(token: string, status?: string) {
+    return api.get("/bind/pending", {
+        params: status === undefined ? {} : {"status": status},
+        headers: {
+            "token": token
+        }
+    })
+}
+
+export function approvePendingBind(id: number, token: string) {
+    return api.post("/bind/pending/approve", {
+        "id": id
+    }, {
+        headers: {
+            "token": token
+        }
+    },)
+}
+
+export function rejectPendingBind(id: number, token: string) {
+    return api.post("/bind/pending/reject", {
+        "id": id
+    }, {
+        headers: {
+            "token": token
+        }
+    },)
+}
+
+export function setPlayerPassword(id: string, token: string, password?: string) {
+    return api.post("/player/password", password === undefined ? {
+        "id": id
+    } : {
+        "id": id,
+        "password": password
+    }, {
+        headers: {
+            "token": token
+        }
+    },)
+}
+
+export function revealPlayerPassword(id: string, token: string) {
+    return api.post("/player/password/reveal", {
+        "id": id
+    }, {
+        headers: {
+            "token": token
+        }
+    },)
+}
+
+export function getToggles(token: string) {
+    return api.get("/toggle", {
+        headers: {
+            "token": token
+        }
+    })
+}
+
+export function setToggles(toggles: Partial<Toggles>, token: string) {
+    return api.post("/toggle", toggles, {
+        headers: {
+            "token": token
+        }
+    },)
+}
+
+// EventSource cannot send the token header, so read the SSE body by hand
+export async function streamStatus(token: string, onEvent: (data: unknown) => void) {
+    const response = await fetch("/status/stream", {
+        headers: {
+            "token": token
+        }
+    })
+    if (!response.ok || response.body === null) {
+        throw new Error(`Status stream failed with ${response.status}`)
+    }
+    const reader = response.body.pipeThrough(new TextDecoderStream()).getReader()
+    let buffer = ""
+    while (true) {
+        const {value, done} = await reader.read()
+        if (done) {
+            return
+        }
+        buffer += value
+        let end = buffer.indexOf("\n\n")
+        while (end !== -1) {
+            const message = buffer.slice(0, end)
+            buffer = buffer.slice(end + 2)
+            for (const line of message.split("\n")) {
+                if (line.startsWith("data: ")) {
+                    onEvent(JSON.parse(line.slice(6)))
+                }
+            }
+            end = buffer.indexOf("\n\n")
+        }
+    }
+}
@@ -1,21 +1,38 @@
 mod bind;
+mod caddy;
 mod check;
 mod desktop;
+mod drift;
+mod fingerprint;
+mod fsutil;
 mod clean;
 mod monitor;
+mod net;
+mod outbox;
 mod session;
+mod state;
+mod status;
 mod sync;
+mod telemetry;
 
 pub use bind::{BindOptions, bind_ip};
 pub use check::{check_permission, check_prerequisite};
 pub use clean::clean_user;
 pub use monitor::do_monitor;
-pub use session::{autologin_session, terminate_sessions};
+pub use session::{autologin_session, lock_sessions, terminate_sessions};
+pub use status::print_status;
 pub use sync::sync_info;
 
 use serde::Deserialize;
 
-fn build_server_http_client() -> anyhow::Result<reqwest::blocking::Client> {
+/// Certificates for talking to the server, the client identity only exists after bind
+struct ServerTls {
+    ca_cert_path: String,
+    ca_cert: reqwest::Certificate,
+    identity: Option<reqwest::Identity>,
+}
+
+fn load_server_tls() -> anyhow::Result<ServerTls> {
     let config = crate::GLOBAL_CONFIG
         .get()
         .ok_or_else(|| anyhow::Error::msg("Global config not initialized"))?;
@@ -32,16 +49,70 @@ fn build_server_http_client() -> anyhow::Result<reqwest::blocking::Client> {
         ))
     })?;
 
-    reqwest::blocking::Client::builder()
-        .tls_certs_only(vec![ca_cert])
-        .tls_danger_accept_invalid_hostnames(true)
-        .https_only(true)
-        .build()
-        .map_err(|err| {
+    // The client certificate only exists after bind
+    let cert_path = &client_config.tls_client_cert_path;
+    let key_path = &client_config.tls_client_key_path;
+    let identity = if let (Ok(mut identity_pem), Ok(key_pem)) =
+        (std::fs::read(cert_path), std::fs::read(key_path))
+    {
+        identity_pem.extend_from_slice(&key_pem);
+        Some(reqwest::Identity::from_pem(&identity_pem).map_err(|err| {
             anyhow::Error::msg(format!(
-                "Failed to build HTTPS client using CA certificate {ca_cert_path}: {err:?}"
+                "Failed to parse client certificate {cert_path} with key {key_path}: {err}"
             ))
-        })
+        })?)
+    } else {
+        tracing::debug!(
+            "No client certificate at {}, machine not bound yet",
+            cert_path
+        );
+        None
+    };
+
+    Ok(ServerTls {
+        ca_cert_path: ca_cert_path.clone(),
+        ca_cert,
+        identity,
+    })
+}
+
+fn build_server_http_client() -> anyhow::Result<reqwest::blocking::Client> {
+    let tls = load_server_tls()?;
+    let mut builder = reqwest::blocking::Client::builder()
+        .tls_certs_only(vec![tls.ca_cert])
+        .tls_danger_accept_invalid_hostnames(true)
+        .https_only(true);
+    if let Some(identity) = tls.identity {
+        builder = builder.identity(identity);
+    }
+
+    builder.build().map_err(|err| {
+        anyhow::Error::msg(format!(
+            "Failed to build HTTPS client using CA certificate {}: {err:?}",
+            tls.ca_cert_path
+        ))
+    })
+}
+
+/// Same as [`build_server_http_client`] for use on the tokio runtime, with the blocking
+/// client's default timeout since the async one has none
+fn build_async_server_http_client() -> anyhow::Result<reqwest::Client> {
+    let tls = load_server_tls()?;
+    let mut builder = reqwest::Client::builder()
+        .tls_certs_only(vec![tls.ca_cert])
+        .tls_danger_accept_invalid_hostnames(true)
+        .https_only(true)
+        .timeout(std::time::Duration::from_secs(30));
+    if let Some(identity) = tls.identity {
+        builder = builder.identity(identity);
+    }
+
+    builder.build().map_err(|err| {
+        anyhow::Error::msg(format!(
+            "Failed to build HTTPS client using CA certificate {}: {err:?}",
+            tls.ca_cert_path
+        ))
+    })
 }
 
 #[derive(Deserialize)]
@@ -1,7 +1,10 @@
 use std::{
+    fs::OpenOptions,
+    io::Write,
     net::IpAddr,
-    os::unix::process::CommandExt,
+    os::unix::{fs::OpenOptionsExt, process::CommandExt},
     process::{self, Command, Stdio},
+    time::{Duration, Instant},
 };
 
 use anyhow::bail;
@@ -12,6 +15,8 @@ use tracing_unwrap::OptionExt;
 pub struct BindOptions {
     pub id: Option<String>,
     pub prompt: bool,
+    /// Let the server resolve the ID, falling back to the prompt when it cannot
+    pub auto: bool,
     pub background: bool,
 }
 
@@ -23,56 +28,6 @@ enum BindInput {
     },
 }
 
-pub fn get_mac(target_ip: String) -> anyhow::Result<String> {
-    if target_ip == "localhost" {
-        bail!("Can't get MAC of loop addr localhost");
-    }
-
-    if let Ok(ip) = target_ip.parse::<IpAddr>()
-        && ip.is_loopback()
-    {
-        bail!("Can't get MAC of loop addr {}", target_ip);
-    }
-
-    let full_cmd = format!(
-        r#"
-        ip route get {} | awk '{{for(i=1;i<=NF;i++) if($i=="dev") print $(i+1)}}' | xargs -r -I{{}} ip -o link show {{}} | awk '{{for(i=1;i<=NF;i++) if($i=="link/ether") print $(i+1)}}'
-        "#,
-        target_ip
-    );
-    tracing::debug!("Full command: {}", full_cmd);
-    let output = Command::new("sh").arg("-c").arg(full_cmd).output()?;
-    let errout = String::from_utf8_lossy(&output.stderr).trim().to_string();
-    let stdout = String::from_utf8_lossy(&output.stdout).trim().to_string();
-    if output.status.success() && !stdout.is_empty() {
-        return Ok(stdout);
-    }
-
-    let err = format!(
-        "exit status: {:?}\nstderr: \n{}\nstdout: \n{}",
-        output.status.code(),
-        errout,
-        stdout
-    );
-    bail!(err)
-}
-
-fn get_netinfo() -> anyhow::Result<String> {
-    let output = Command::new("sh").arg("-c").arg("ip addr").output()?;
-    let stdout = String::from_utf8_lossy(&output.stdout).trim().to_string();
-    let errout = String::from_utf8_lossy(&output.stderr).trim().to_string();
-    if output.status.success() && !stdout.is_empty() {
-        return Ok(stdout);
-    }
-    let err = format!(
-        "exit status: {:?}\nstderr: \n{}\nstdout: \n{}",
-        output.status.code(),
-        errout,
-        stdout
-    );
-    bail!(err)
-}
-
 fn validate_direct_connection(url: &String) -> anyhow::Result<bool> {
     let request_url = format!("{}/ip", url);
 
@@ -102,33 +57,190 @@ fn validate_direct_connection(url: &String) -> anyhow::Result<bool> {
         }
     }
 
-    // Fetch IP info from local
-    let netinfo = get_netinfo()?;
-
-    Ok(netinfo.contains(ip.ip.as_str()))
+    let observed_ip = ip
+        .ip
+        .parse::<IpAddr>()
+        .map_err(|_| anyhow::Error::msg(format!("Server returned invalid IP {}", ip.ip)))?;
+    super::net::has_local_address(observed_ip)
 }
 
 #[derive(Serialize)]
 struct RequestBody {
     mac: String,
-    id: String,
+    /// Left out for auto bind, the server resolves it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
     client_version: String,
+    /// Lets the server check this machine against the seat map
+    hostname: Option<String>,
+    /// Recognizes this machine when its MAC changes
+    fingerprint: String,
+}
+
+#[derive(Deserialize)]
+struct BindResponseBody {
+    #[serde(default)]
+    id: Option<String>,
+    cert: String,
+    key: String,
+}
+
+fn write_private_file(path: &str, content: &str) -> anyhow::Result<()> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)
+        .map_err(|err| anyhow::Error::msg(format!("Failed to create {path}, err {err}")))?;
+    file.write_all(content.as_bytes())?;
+    file.sync_all()?;
+    Ok(())
+}
+
+/// Keep the certificate issued at bind, it identifies this machine on sync and report
+fn store_client_certificate(response: BindResponseBody) -> anyhow::Result<()> {
+    let client_config = &crate::GLOBAL_CONFIG
+        .get()
+        .expect_or_log("Global config not initialized")
+        .client;
+    write_private_file(&client_config.tls_client_key_path, &response.key)?;
+    write_private_file(&client_config.tls_client_cert_path, &response.cert)?;
+    tracing::info!(
+        "Client certificate stored to {}",
+        client_config.tls_client_cert_path
+    );
+    super::state::record_bound(response.id.as_deref());
+    Ok(())
+}
+
+/// Error body of `/bind`, `reason` and the bound fields are only set for a refused bind
+#[derive(Deserialize)]
+struct BindErrorResponse {
+    msg: String,
+    error: String,
+    #[serde(default)]
+    reason: Option<String>,
+    #[serde(default)]
+    id: Option<String>,
+    #[serde(default)]
+    expected_hostname: Option<String>,
+}
+
+/// Explain a refused or queued bind in words the person at the seat can act on
+fn describe_bind_conflict(
+    id: &str,
+    reason: &str,
+    bound_mac: Option<&str>,
+    bound_id: Option<&str>,
+    expected_hostname: Option<&str>,
+) -> Option<String> {
+    let description = match reason {
+        "unknown_id" => {
+            format!("Contestant ID {id} does not exist, check it for typos and try again")
+        }
+        "id_bound" => format!(
+            "Contestant ID {id} is already bound to another machine ({})",
+            bound_mac.unwrap_or("unknown MAC")
+        ),
+        "mac_changed" => format!(
+            "Contestant ID {id} is bound to this machine under another network card ({}), \
+             ask the staff to approve the change",
+            bound_mac.unwrap_or("unknown MAC")
+        ),
+        "mac_bound" => format!(
+            "This machine is already bound to contestant ID {}",
+            bound_id.unwrap_or("unknown")
+        ),
+        "certificate_required" => format!(
+            "This machine is already bound to contestant ID {} but its client certificate is \
+             missing, ask the staff to approve the rebind",
+            bound_id.unwrap_or("unknown")
+        ),
+        "unknown_seat" => {
+            "The IP of this machine is not in the seat map, ask the staff for help".to_string()
+        }
+        "seat_mismatch" => format!(
+            "This seat expects machine {}, ask the staff to check whether machines were swapped",
+            expected_hostname.unwrap_or("unknown")
+        ),
+        "unresolved" => {
+            "No contestant ID is assigned to this machine, enter it manually".to_string()
+        }
+        _ => return None,
+    };
+    Some(description)
+}
+
+/// Conflicting bind queued on the server until staff approve or reject it
+#[derive(Deserialize)]
+struct QueuedBind {
+    id: String,
+    reason: String,
+    #[serde(default)]
+    bound_mac: Option<String>,
+    #[serde(default)]
+    bound_id: Option<String>,
+    request_id: i32,
+    secret: String,
+}
+
+impl QueuedBind {
+    fn description(&self) -> String {
+        let conflict = describe_bind_conflict(
+            &self.id,
+            &self.reason,
+            self.bound_mac.as_deref(),
+            self.bound_id.as_deref(),
+            None,
+        )
+        .unwrap_or_else(|| format!("Bind to contestant ID {} conflicts", self.id));
+        format!("{conflict}\nThe bind request is waiting for staff approval")
+    }
 }
 
-fn send_bind_req(url: &String, id: &str, mac: &str) -> anyhow::Result<()> {
-    let request_url = format!("{}/bind", url);
+enum BindOutcome {
+    Bound,
+    Pending(QueuedBind),
+    /// Auto bind found no ID for this machine
+    Unresolved,
+}
+
+/// Bind to the given ID, or let the server resolve it through `/bind/auto` when it is `None`
+fn send_bind_req(url: &String, id: Option<&str>, mac: &str) -> anyhow::Result<BindOutcome> {
+    let request_url = match id {
+        Some(_) => format!("{}/bind", url),
+        None => format!("{}/bind/auto", url),
+    };
     let client = super::build_server_http_client()?;
     let body = RequestBody {
         mac: mac.to_string(),
-        id: id.to_string(),
+        id: id.map(str::to_string),
         client_version: version!().to_string(),
+        hostname: super::desktop::get_hostname().ok(),
+        fingerprint: super::fingerprint::machine_fingerprint(mac),
     };
 
     let response = client.post(request_url).json(&body).send()?;
     match response.status() {
         StatusCode::OK => {}
+        StatusCode::ACCEPTED => return Ok(BindOutcome::Pending(response.json()?)),
         other => {
-            let error: crate::client::ErrorResponse = response.json()?;
+            let error: BindErrorResponse = response.json()?;
+            if id.is_none() && error.reason.as_deref() == Some("unresolved") {
+                return Ok(BindOutcome::Unresolved);
+            }
+            if let Some(reason) = &error.reason
+                && let Some(description) = describe_bind_conflict(
+                    error.id.as_deref().or(id).unwrap_or("unknown"),
+                    reason,
+                    None,
+                    None,
+                    error.expected_hostname.as_deref(),
+                )
+            {
+                bail!(description)
+            }
 
             bail!(
                 "Wrong response code {}, error {} {}",
@@ -139,10 +251,96 @@ fn send_bind_req(url: &String, id: &str, mac: &str) -> anyhow::Result<()> {
         }
     }
 
-    anyhow::Ok(())
+    let response: BindResponseBody = response.json()?;
+    if let Some(bound_id) = &response.id {
+        tracing::info!("Bound to contestant ID {}", bound_id);
+    }
+    store_client_certificate(response)?;
+    Ok(BindOutcome::Bound)
 }
 
-fn perform_bind(id: &str) -> anyhow::Result<()> {
+#[derive(Serialize)]
+struct BindDecisionRequestBody<'a> {
+    request_id: i32,
+    secret: &'a str,
+}
+
+#[derive(Deserialize)]
+struct BindDecisionResponseBody {
+    status: String,
+    cert: Option<String>,
+    key: Option<String>,
+}
+
+const BIND_DECISION_POLL_INTERVAL: Duration = Duration::from_secs(5);
+const BIND_DECISION_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+/// Poll the server until staff decide on the queued bind
+fn wait_for_bind_decision(queued: &QueuedBind) -> anyhow::Result<()> {
+    let base_url = &crate::GLOBAL_CONFIG
+        .get()
+        .expect_or_log("Global config not initialized")
+        .client
+        .server_addr;
+    let request_url = format!("{}/bind/decision", base_url);
+    let client = super::build_server_http_client()?;
+    let body = BindDecisionRequestBody {
+        request_id: queued.request_id,
+        secret: &queued.secret,
+    };
+
+    tracing::info!(
+        "Bind request {} waiting for staff approval",
+        queued.request_id
+    );
+    let deadline = Instant::now() + BIND_DECISION_TIMEOUT;
+    while Instant::now() < deadline {
+        std::thread::sleep(BIND_DECISION_POLL_INTERVAL);
+        let response = match client.post(&request_url).json(&body).send() {
+            Ok(response) => response,
+            Err(err) => {
+                tracing::warn!("Failed to poll bind decision, err {}", err);
+                continue;
+            }
+        };
+        match response.status() {
+            StatusCode::OK => {}
+            other => {
+                let error: crate::client::ErrorResponse = response.json()?;
+
+                bail!(
+                    "Wrong response code {}, error {} {}",
+                    other,
+                    error.msg,
+                    error.error
+                )
+            }
+        }
+
+        let decision: BindDecisionResponseBody = response.json()?;
+        match decision.status.as_str() {
+            "approved" => {
+                let (Some(cert), Some(key)) = (decision.cert, decision.key) else {
+                    bail!("Bind request approved without a client certificate");
+                };
+                return store_client_certificate(BindResponseBody {
+                    id: Some(queued.id.clone()),
+                    cert,
+                    key,
+                });
+            }
+            "rejected" => bail!("Bind request rejected by staff"),
+            _ => {}
+        }
+    }
+    bail!(
+        "No decision on bind request {} after {} minutes",
+        queued.request_id,
+        BIND_DECISION_TIMEOUT.as_secs() / 60
+    )
+}
+
+fn perform_bind(id: Option<&str>) -> anyhow::Result<BindOutcome> {
     let base_url = &crate::GLOBAL_CONFIG
         .get()
         .unwrap_or_log()
@@ -163,19 +361,22 @@ fn perform_bind(id: &str) -> anyhow::Result<()> {
         tracing::warn!("Skip check enabled, will continue procedding")
     }
 
-    let parsed_url = reqwest::Url::parse(base_url)
-        .map_err(|_| anyhow::Error::msg("Failed to parse base URL"))?;
-    let target_ip = parsed_url
-        .host_str()
-        .expect_or_log("Failed to get host str from base URL")
-        .to_string();
-    let mac = get_mac(target_ip)?;
-    tracing::info!("Current device MAC is {}", mac);
+    let interface = super::net::server_interface()?;
+    tracing::info!("Current device MAC is {}", interface);
+    let mac = interface.mac;
 
     match send_bind_req(base_url, id, &mac) {
-        Ok(_) => {
+        Ok(BindOutcome::Bound) => {
             tracing::info!("Bind success!");
-            Ok(())
+            Ok(BindOutcome::Bound)
+        }
+        Ok(BindOutcome::Pending(queued)) => {
+            tracing::warn!("Bind conflict queued: {}", queued.description());
+            Ok(BindOutcome::Pending(queued))
+        }
+        Ok(BindOutcome::Unresolved) => {
+            tracing::warn!("Server could not resolve an ID for this machine");
+            Ok(BindOutcome::Unresolved)
         }
         Err(e) => {
             tracing::error!("Bind FAILED!");
@@ -204,7 +405,28 @@ fn spawn_background_bind(config_path: &str) -> anyhow::Result<()> {
 }
 
 pub fn bind_ip(options: BindOptions, config_path: &str) -> anyhow::Result<()> {
-    if options.prompt && !options.background {
+    let result = run_bind(options, config_path);
+    // Successful binds are recorded along the certificate, which knows the bound ID
+    if result.is_err() {
+        super::state::record(super::state::StateAction::Bind, &result);
+    }
+    result
+}
+
+fn run_bind(options: BindOptions, config_path: &str) -> anyhow::Result<()> {
+    let mut prompt = options.prompt;
+    if options.auto && !options.background {
+        match perform_bind(None)? {
+            BindOutcome::Bound => return Ok(()),
+            BindOutcome::Pending(queued) => return wait_for_bind_decision(&queued),
+            BindOutcome::Unresolved => {
+                tracing::warn!("Falling back to prompt for the ID");
+                prompt = true;
+            }
+        }
+    }
+
+    if prompt && !options.background {
         // Verify prerequisites in parent so failures are visible to pssh/SSH
         let player_user = crate::GLOBAL_CONFIG
             .get()
@@ -218,7 +440,7 @@ pub fn bind_ip(options: BindOptions, config_path: &str) -> anyhow::Result<()> {
         process::exit(0);
     }
 
-    let bind_input = match (options.id, options.prompt, options.background) {
+    let bind_input = match (options.id, prompt, options.background) {
         (Some(id), _, _) => BindInput::Cli(id),
         (None, true, true) => {
             let player_user = crate::GLOBAL_CONFIG
@@ -244,9 +466,24 @@ pub fn bind_ip(options: BindOptions, config_path: &str) -> anyhow::Result<()> {
         .clone();
 
     match bind_input {
-        BindInput::Cli(id) => perform_bind(&id),
+        BindInput::Cli(id) => match perform_bind(Some(&id))? {
+            BindOutcome::Bound => Ok(()),
+            BindOutcome::Pending(queued) => wait_for_bind_decision(&queued),
+            BindOutcome::Unresolved => bail!("Bind to ID {} was not resolved", id),
+        },
         BindInput::Gui { id, desktop_env } => {
-            let bind_result = perform_bind(&id);
+            let bind_result = perform_bind(Some(&id)).and_then(|outcome| match outcome {
+                BindOutcome::Bound => Ok(()),
+                BindOutcome::Pending(queued) => {
+                    super::desktop::show_bind_waiting(
+                        &player_user,
+                        &desktop_env,
+                        &queued.description(),
+                    );
+                    wait_for_bind_decision(&queued)
+                }
+                BindOutcome::Unresolved => bail!("Bind to ID {} was not resolved", id),
+            });
             let result_text = match &bind_result {
                 Ok(_) => format!("Bind succeeded for contestant ID {id}"),
                 Err(err) => format!("Bind failed: {err:#}"),
@@ -0,0 +1,334 @@
+use std::{
+    fs::{OpenOptions, remove_file},
+    io::Write,
+    os::unix::fs::OpenOptionsExt,
+    process::Command,
+    time::Duration,
+};
+
+use anyhow::bail;
+use reqwest::header::CONTENT_TYPE;
+use serde::Deserialize;
+use serde_json::Value;
+use tracing_unwrap::OptionExt;
+
+#[derive(Deserialize)]
+struct AdaptResponse {
+    result: Value,
+    #[serde(default)]
+    warnings: Vec<Value>,
+}
+
+fn configured_admin_addr() -> &'static str {
+    &crate::GLOBAL_CONFIG
+        .get()
+        .expect_or_log("Global config not initialized")
+        .client
+        .caddy_admin_addr
+}
+
+fn admin_url(admin_addr: &str, path: &str) -> String {
+    format!("http://{admin_addr}{path}")
+}
+
+fn build_admin_http_client() -> anyhow::Result<reqwest::blocking::Client> {
+    reqwest::blocking::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+        .map_err(|err| anyhow::Error::msg(format!("Failed to build Caddy admin client: {err:?}")))
+}
+
+fn check_response(
+    response: reqwest::blocking::Response,
+    action: &str,
+) -> anyhow::Result<reqwest::blocking::Response> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let body = response.text().unwrap_or_default();
+    bail!("Caddy admin API failed to {action}, status {status}, body {body}")
+}
+
+fn read_config_at(client: &reqwest::blocking::Client, admin_addr: &str) -> anyhow::Result<Value> {
+    let response = client.get(admin_url(admin_addr, "/config/")).send()?;
+    Ok(check_response(response, "read back config")?.json()?)
+}
+
+/// Config Caddy is running, whether loaded through the admin API or adapted from the Caddyfile
+pub fn read_active_config() -> anyhow::Result<Value> {
+    read_config_at(&build_admin_http_client()?, configured_admin_addr())
+}
+
+/// Adapt the Caddyfile to JSON, load it in one request and read it back,
+/// Caddy either switches to the new config completely or keeps the old one.
+pub fn load_caddyfile(caddyfile: &str) -> anyhow::Result<()> {
+    load_caddyfile_at(configured_admin_addr(), caddyfile)
+}
+
+fn load_caddyfile_at(admin_addr: &str, caddyfile: &str) -> anyhow::Result<()> {
+    let client = build_admin_http_client()?;
+
+    let response = client
+        .post(admin_url(admin_addr, "/adapt"))
+        .header(CONTENT_TYPE, "text/caddyfile")
+        .body(caddyfile.to_string())
+        .send()?;
+    let adapted: AdaptResponse = check_response(response, "adapt Caddyfile")?.json()?;
+    for warning in &adapted.warnings {
+        tracing::warn!("Caddyfile adapt warning {}", warning);
+    }
+
+    let response = client
+        .post(admin_url(admin_addr, "/load"))
+        .json(&adapted.result)
+        .send()?;
+    check_response(response, "load config")?;
+
+    let active = read_config_at(&client, admin_addr)?;
+    if active != adapted.result {
+        bail!("Config read back from Caddy admin API does not match the loaded one");
+    }
+
+    tracing::info!("Caddy config loaded and verified through admin API");
+    Ok(())
+}
+
+/// Characters letting a value break out of a quoted token or open a placeholder in the Caddyfile
+const UNSAFE_VALUE_CHARS: [char; 4] = ['"', '\\', '{', '}'];
+
+/// Replace every `{{name}}` in the template, unknown names are rejected so a typo
+/// does not end up as a literal in the proxy config. Values that could inject directives,
+/// e.g. a username with a quote or a newline, are refused.
+pub fn render_template(template: &str, variables: &[(&str, &str)]) -> anyhow::Result<String> {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        rendered.push_str(&rest[..start]);
+        let Some(length) = rest[start + 2..].find("}}") else {
+            bail!("Unclosed variable in Caddyfile template");
+        };
+        let name = rest[start + 2..start + 2 + length].trim();
+        match variables.iter().find(|(key, _)| *key == name) {
+            Some((_, value))
+                if value
+                    .chars()
+                    .any(|c| c.is_control() || UNSAFE_VALUE_CHARS.contains(&c)) =>
+            {
+                bail!(
+                    "Value of {} contains a quote, backslash, brace or control character not allowed in the Caddyfile",
+                    name
+                )
+            }
+            Some((_, value)) => rendered.push_str(value),
+            None => bail!("Unknown variable {} in Caddyfile template", name),
+        }
+        rest = &rest[start + 2 + length + 2..];
+    }
+    rendered.push_str(rest);
+    Ok(rendered)
+}
+
+/// Run `caddy validate` on the rendered config, using a root only file next to the live Caddyfile
+pub fn validate_caddyfile(caddyfile: &str, caddyfile_path: &str) -> anyhow::Result<()> {
+    let validate_path = format!("{caddyfile_path}.validate");
+    let _ = remove_file(&validate_path);
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&validate_path)
+        .map_err(|err| {
+            anyhow::Error::msg(format!("Failed to create {validate_path}, err {err}"))
+        })?;
+    file.write_all(caddyfile.as_bytes())?;
+    drop(file);
+
+    let output = Command::new("caddy")
+        .arg("validate")
+        .arg("--adapter")
+        .arg("caddyfile")
+        .arg("--config")
+        .arg(&validate_path)
+        .output();
+    let _ = remove_file(&validate_path);
+
+    match output {
+        Ok(result) if result.status.success() => Ok(()),
+        Ok(result) => {
+            let stderr = String::from_utf8_lossy(&result.stderr).trim().to_string();
+            bail!("Caddyfile validation failed: {}", stderr)
+        }
+        Err(err) => bail!("Failed to run caddy validate, err {}", err),
+    }
+}
+
+/// Request `https://<reverse_addr>/` through the freshly applied proxy,
+/// a connection error or a 5xx means contestants cannot reach DOMjudge.
+pub fn probe_reverse_proxy() -> anyhow::Result<()> {
+    let reverse_addr = &crate::GLOBAL_CONFIG
+        .get()
+        .expect_or_log("Global config not initialized")
+        .client
+        .reverse_addr;
+    let client = reqwest::blocking::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .tls_danger_accept_invalid_certs(true)
+        .build()
+        .map_err(|err| anyhow::Error::msg(format!("Failed to build probe client: {err:?}")))?;
+
+    let url = format!("https://{reverse_addr}/");
+    let mut last_error = String::new();
+    // Caddy may need a moment to bind after a reload
+    for attempt in 1..=3 {
+        match client.get(&url).send() {
+            Ok(response) if !response.status().is_server_error() => {
+                tracing::info!("Reverse proxy probe {} returned {}", url, response.status());
+                return Ok(());
+            }
+            Ok(response) => last_error = format!("status {}", response.status()),
+            Err(err) => last_error = format!("{err:#}"),
+        }
+        tracing::warn!(
+            "Reverse proxy probe {} attempt {} failed: {}",
+            url,
+            attempt,
+            last_error
+        );
+        std::thread::sleep(Duration::from_secs(1));
+    }
+    bail!("Reverse proxy probe {} failed: {}", url, last_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+        sync::{Arc, Mutex},
+        thread,
+    };
+
+    use serde_json::{Value, json};
+
+    /// Minimal stand-in for the Caddy admin API serving `/adapt`, `/load` and `/config/`.
+    /// `/config/` answers with `read_back` when given, otherwise with the last loaded config.
+    fn spawn_admin_stub(adapted: Value, read_back: Option<Value>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let loaded = Arc::new(Mutex::new(Value::Null));
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { break };
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                // The client keeps the connection alive across requests
+                loop {
+                    let mut request_line = String::new();
+                    if reader.read_line(&mut request_line).unwrap_or(0) == 0 {
+                        break;
+                    }
+                    let mut content_length = 0;
+                    loop {
+                        let mut header = String::new();
+                        reader.read_line(&mut header).unwrap();
+                        let header = header.trim_end();
+                        if header.is_empty() {
+                            break;
+                        }
+                        if let Some((name, value)) = header.split_once(':')
+                            && name.eq_ignore_ascii_case("content-length")
+                        {
+                            content_length = value.trim().parse().unwrap();
+                        }
+                    }
+                    let mut body = vec![0; content_length];
+                    reader.read_exact(&mut body).unwrap();
+
+                    let path = request_line.split_whitespace().nth(1).unwrap_or_default();
+                    let response = match path {
+                        "/adapt" => json!({ "result": adapted, "warnings": [] }),
+                        "/load" => {
+                            *loaded.lock().unwrap() = serde_json::from_slice(&body).unwrap();
+                            Value::Null
+                        }
+                        "/config/" => read_back
+                            .clone()
+                            .unwrap_or_else(|| loaded.lock().unwrap().clone()),
+                        _ => Value::Null,
+                    };
+                    let response = response.to_string();
+                    write!(
+                        stream,
+                        "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{}",
+                        response.len(),
+                        response
+                    )
+                    .unwrap();
+                }
+            }
+        });
+        addr
+    }
+
+    fn adapted_config() -> Value {
+        json!({ "apps": { "http": { "servers": { "srv0": { "listen": [":443"] } } } } })
+    }
+
+    #[test]
+    fn render_template_substitutes_variables() {
+        let rendered = super::render_template(
+            "header_up X-DOMjudge-Login \"{{ username }}\"",
+            &[("username", "team001")],
+        )
+        .unwrap();
+        assert_eq!(rendered, "header_up X-DOMjudge-Login \"team001\"");
+    }
+
+    #[test]
+    fn render_template_rejects_unknown_variable() {
+        let err = super::render_template("{{usrname}}", &[("username", "team001")]).unwrap_err();
+        assert!(
+            err.to_string().contains("Unknown variable usrname"),
+            "{err:#}"
+        );
+    }
+
+    #[test]
+    fn render_template_rejects_injected_values() {
+        let template = "header_up X-DOMjudge-Login \"{{username}}\"";
+        for username in [
+            "team\"\n\trespond 200",
+            "team\nrespond 200",
+            "team\\",
+            "{env.SECRET}",
+            "team}",
+        ] {
+            let err = super::render_template(template, &[("username", username)]).unwrap_err();
+            assert!(err.to_string().contains("not allowed"), "{err:#}");
+        }
+    }
+
+    #[test]
+    fn load_caddyfile_verifies_read_back_config() {
+        let addr = spawn_admin_stub(adapted_config(), None);
+        super::load_caddyfile_at(&addr, "tester.icpc {\n}\n").unwrap();
+    }
+
+    #[test]
+    fn read_config_returns_loaded_config() {
+        let addr = spawn_admin_stub(adapted_config(), None);
+        super::load_caddyfile_at(&addr, "tester.icpc {\n}\n").unwrap();
+        let client = super::build_admin_http_client().unwrap();
+        assert_eq!(
+            super::read_config_at(&client, &addr).unwrap(),
+            adapted_config()
+        );
+    }
+
+    #[test]
+    fn load_caddyfile_rejects_mismatched_read_back() {
+        let addr = spawn_admin_stub(adapted_config(), Some(json!({ "apps": {} })));
+        let err = super::load_caddyfile_at(&addr, "tester.icpc {\n}\n").unwrap_err();
+        assert!(err.to_string().contains("does not match"), "{err:#}");
+    }
+}
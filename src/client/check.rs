@@ -14,7 +14,8 @@ fn can_sudo_help(command: &str) -> bool {
         .unwrap_or(false)
 }
 
-pub fn check_permission(path: String) -> bool {
+/// Reload permission is only required when Caddy is not driven through its admin API
+pub fn check_permission(path: String, require_reload: bool) -> bool {
     #[cfg(not(target_os = "windows"))]
     {
         let useradd_ok = can_sudo_help("useradd");
@@ -25,8 +26,11 @@ pub fn check_permission(path: String) -> bool {
             return false;
         }
         if !systemctl_reload_ok {
-            tracing::error!("No permission to reload service!");
-            return false;
+            if require_reload {
+                tracing::error!("No permission to reload service!");
+                return false;
+            }
+            tracing::warn!("No permission to reload service, Caddyfile fallback will not work!");
         }
     }
     OpenOptions::new().write(true).open(path).is_ok()
@@ -4,6 +4,12 @@ use anyhow::{Ok, bail};
 use tracing_unwrap::OptionExt;
 
 pub fn clean_user() -> anyhow::Result<()> {
+    let result = recreate_user();
+    super::state::record(super::state::StateAction::Clean, &result);
+    result
+}
+
+fn recreate_user() -> anyhow::Result<()> {
     let user_name = crate::GLOBAL_CONFIG
         .get()
         .expect_or_log("Global config not initialized")
@@ -79,7 +85,7 @@ pub fn clean_user() -> anyhow::Result<()> {
         .arg("-c")
         .arg(format!(
             "echo '{}:{}' | sudo chpasswd",
-            &user_name, &user_password
+            user_name, user_password
         ))
         .output()
         .expect("failed to execute process");
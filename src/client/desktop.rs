@@ -60,7 +60,7 @@ fn lookup_user_id(player_user: &str) -> anyhow::Result<u32> {
         .with_context(|| format!("Failed to parse UID for user {player_user}"))
 }
 
-fn lookup_home_dir(player_user: &str) -> anyhow::Result<String> {
+pub fn lookup_home_dir(player_user: &str) -> anyhow::Result<String> {
     let output = get_command_output(
         {
             let mut command = safe_command("getent");
@@ -152,7 +152,7 @@ fn is_valid_bind_id(id: &str) -> bool {
             .all(|ch| ch.is_ascii_alphanumeric() || matches!(ch, '.' | '_' | '-'))
 }
 
-fn get_hostname() -> anyhow::Result<String> {
+pub fn get_hostname() -> anyhow::Result<String> {
     let output = get_command_output(
         safe_command("hostname"),
         "hostname",
@@ -348,6 +348,32 @@ pub fn prompt_bind_id(player_user: &str) -> anyhow::Result<PromptResult> {
     }
 }
 
+/// Tell the person at the seat that the bind waits for staff, closes by itself so polling continues
+pub fn show_bind_waiting(player_user: &str, env: &DesktopSessionEnv, text: &str) {
+    let args = [
+        "--info".to_string(),
+        "--title=Natsume Bind".to_string(),
+        format!("--text={text}"),
+        "--width=420".to_string(),
+        "--timeout=30".to_string(),
+        "--timeout-indicator=bottom".to_string(),
+    ];
+    let arg_refs: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    match run_yad_as_user(player_user, env, &arg_refs) {
+        // 70 is returned when the dialog timed out
+        Ok(output) => match output.status.code() {
+            Some(0) | Some(5) | Some(70) => {}
+            Some(code) => {
+                let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+                tracing::warn!("Failed to show bind waiting dialog, exit code {code}: {stderr}");
+            }
+            None => tracing::warn!("Failed to show bind waiting dialog: terminated by signal"),
+        },
+        Err(err) => tracing::warn!("Failed to show bind waiting dialog: {err:#}"),
+    }
+}
+
 pub fn show_bind_result(
     player_user: &str,
     env: &DesktopSessionEnv,
@@ -0,0 +1,121 @@
+use std::{
+    fs::{read, read_to_string},
+    path::Path,
+};
+
+use anyhow::bail;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use tracing_unwrap::OptionExt;
+
+use super::caddy;
+use super::fsutil::write_atomic;
+
+fn hash_path() -> String {
+    crate::GLOBAL_CONFIG
+        .get()
+        .expect_or_log("Global config not initialized")
+        .client
+        .caddyfile_hash_path
+        .clone()
+}
+
+fn caddy_admin_api() -> bool {
+    crate::GLOBAL_CONFIG
+        .get()
+        .expect_or_log("Global config not initialized")
+        .client
+        .caddy_admin_api
+}
+
+fn hash_content(content: &[u8]) -> String {
+    hex::encode(Sha256::digest(content))
+}
+
+/// Object keys are serialized sorted, so the hash does not depend on the order Caddy returns them
+fn hash_config(config: &Value) -> anyhow::Result<String> {
+    Ok(hash_content(&serde_json::to_vec(config)?))
+}
+
+/// Remember the Caddyfile a sync left in place, the reference for drift checks. With the admin
+/// API the config Caddy runs is recorded as well on a second line, as it can be changed through
+/// the API without touching the file.
+pub fn record_synced_caddyfile(caddyfile: &str) -> anyhow::Result<()> {
+    let mut content = hash_content(caddyfile.as_bytes());
+    if caddy_admin_api() {
+        match caddy::read_active_config().and_then(|config| hash_config(&config)) {
+            Ok(hash) => {
+                content.push('\n');
+                content.push_str(&hash);
+            }
+            Err(err) => tracing::warn!(
+                "Failed to read config through Caddy admin API, only the Caddyfile is checked for drift: {:#}",
+                err
+            ),
+        }
+    }
+    write_atomic(Path::new(&hash_path()), content.as_bytes())
+}
+
+/// Why the live Caddy setup no longer matches the last sync, nothing before the first sync
+pub fn detect_drift() -> Option<String> {
+    let path = hash_path();
+    let recorded = match read_to_string(&path) {
+        Ok(recorded) => recorded,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            tracing::debug!("No Caddyfile hash at {}, machine not synced yet", path);
+            return None;
+        }
+        Err(err) => {
+            tracing::error!("Failed to read Caddyfile hash {}, err {}", path, err);
+            return None;
+        }
+    };
+    let mut recorded = recorded.lines().map(str::trim);
+    let expected_caddyfile = recorded.next().unwrap_or_default();
+    let expected_config = recorded.next().filter(|hash| !hash.is_empty());
+
+    let mut drifts = Vec::new();
+    let caddyfile_path = &crate::GLOBAL_CONFIG
+        .get()
+        .expect_or_log("Global config not initialized")
+        .client
+        .caddyfile;
+    match read(caddyfile_path) {
+        Ok(content) if hash_content(&content) == expected_caddyfile => {}
+        Ok(_) => drifts.push(format!(
+            "Caddyfile {} changed since last sync",
+            caddyfile_path
+        )),
+        Err(err) => drifts.push(format!(
+            "Caddyfile {} unreadable since last sync: {}",
+            caddyfile_path, err
+        )),
+    }
+    if !super::check::check_caddy_active() {
+        drifts.push("Caddy service is not active".to_string());
+    } else if let Some(expected_config) = expected_config
+        && caddy_admin_api()
+    {
+        match caddy::read_active_config().and_then(|config| hash_config(&config)) {
+            Ok(hash) if hash == expected_config => {}
+            Ok(_) => drifts
+                .push("Caddy config changed through the admin API since last sync".to_string()),
+            Err(err) => drifts.push(format!(
+                "Caddy admin API unreadable since last sync: {:#}",
+                err
+            )),
+        }
+    }
+
+    (!drifts.is_empty()).then(|| drifts.join(", "))
+}
+
+/// Resync through the usual load and reload path. A stopped Caddy is left to the staff, the
+/// client may only reload it and sync refuses to run without it.
+pub fn heal_drift() -> anyhow::Result<()> {
+    if !super::check::check_caddy_active() {
+        bail!("Caddy service is not active, start it before resyncing");
+    }
+    super::sync_info()
+}
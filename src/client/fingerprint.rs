@@ -0,0 +1,53 @@
+use std::fs::read_to_string;
+
+use sha2::{Digest, Sha256};
+
+const MACHINE_ID_PATH: &str = "/etc/machine-id";
+/// Only readable by root, which the client runs as
+const PRODUCT_UUID_PATH: &str = "/sys/class/dmi/id/product_uuid";
+
+fn read_identifier(path: &str) -> Option<String> {
+    match read_to_string(path) {
+        Ok(value) if !value.trim().is_empty() => Some(value.trim().to_lowercase()),
+        Ok(_) => {
+            tracing::warn!(
+                "{} is empty, leaving it out of the machine fingerprint",
+                path
+            );
+            None
+        }
+        Err(err) => {
+            tracing::warn!(
+                "Failed to read {}, leaving it out of the machine fingerprint, err {}",
+                path,
+                err
+            );
+            None
+        }
+    }
+}
+
+/// Hash identifying this machine independently of its network card, sent along the MAC so the
+/// server notices a MAC moving to another machine. The MAC is only hashed in when neither
+/// identifier is available.
+pub fn machine_fingerprint(mac: &str) -> String {
+    let identifiers = [
+        read_identifier(MACHINE_ID_PATH),
+        read_identifier(PRODUCT_UUID_PATH),
+    ]
+    .into_iter()
+    .flatten()
+    .collect::<Vec<String>>();
+
+    let mut hasher = Sha256::new();
+    if identifiers.is_empty() {
+        tracing::warn!(
+            "No machine identifier readable, fingerprinting by MAC {} only",
+            mac
+        );
+        hasher.update(mac.to_lowercase());
+    } else {
+        hasher.update(identifiers.join("\n"));
+    }
+    hex::encode(hasher.finalize())
+}
@@ -0,0 +1,47 @@
+use std::{
+    fs::{File, OpenOptions, create_dir_all, rename},
+    io::Write,
+    os::unix::fs::OpenOptionsExt,
+    path::Path,
+};
+
+use anyhow::Context;
+
+/// Exclusive lock on a file next to `path`, held while reading and rewriting it, as commands run
+/// by hand and the monitor service may touch the same file
+pub(super) fn lock_beside(path: &Path) -> anyhow::Result<File> {
+    if let Some(parent) = path.parent() {
+        create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+    }
+    let lock_path = path.with_extension("lock");
+    let lock = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .mode(0o600)
+        .open(&lock_path)
+        .with_context(|| format!("Failed to open lock {}", lock_path.display()))?;
+    lock.lock()?;
+    Ok(lock)
+}
+
+/// Replace a root-only file atomically so a crash never leaves half a file behind
+pub(super) fn write_atomic(path: &Path, content: &[u8]) -> anyhow::Result<()> {
+    if let Some(parent) = path.parent() {
+        create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+    }
+    let tmp_path = path.with_extension("tmp");
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(&tmp_path)
+        .with_context(|| format!("Failed to create {}", tmp_path.display()))?;
+    file.write_all(content)?;
+    file.sync_all()?;
+    rename(&tmp_path, path).with_context(|| format!("Failed to replace {}", path.display()))?;
+    Ok(())
+}
@@ -1,54 +1,108 @@
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 
 use anyhow::bail;
+use rand::Rng;
 use reqwest::StatusCode;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use tracing_unwrap::OptionExt;
 
-use crate::client::bind;
-
 #[derive(Serialize)]
-struct ReportRequest {
+struct ReportRequest<'a> {
     mac: String,
+    fingerprint: String,
     synced: bool,
     client_version: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sync_error: Option<&'a str>,
+    /// Caddy config drift found and resynced since the last delivered heartbeat
+    #[serde(skip_serializing_if = "Option::is_none")]
+    drift: Option<&'a str>,
+    acks: &'a [CommandAck],
+    telemetry: super::telemetry::Telemetry,
 }
 
-pub fn send_report(synced: bool) -> anyhow::Result<()> {
-    let base_url = &crate::GLOBAL_CONFIG
-        .get()
-        .expect_or_log("Global config not initialized")
-        .client
-        .server_addr;
+#[derive(Deserialize, Default)]
+struct ReportResponse {
+    #[serde(default)]
+    commands: Vec<RemoteCommand>,
+}
 
-    let parsed_url = reqwest::Url::parse(base_url)
-        .map_err(|_| anyhow::Error::msg("Failed to parse base URL"))?;
-    let target_ip = parsed_url
-        .host_str()
-        .expect_or_log("Failed to get host str from base URL")
-        .to_string();
+#[derive(Deserialize)]
+struct RemoteCommand {
+    id: i32,
+    command: String,
+}
 
-    let client = super::build_server_http_client()?;
+#[derive(Serialize)]
+struct CommandAck {
+    id: i32,
+    success: bool,
+    message: String,
+}
 
-    let request_url = format!("{}/report", base_url);
+/// Parts of a report read from the machine, collected off the async runtime in the monitor
+struct MachineState {
+    mac: String,
+    fingerprint: String,
+    telemetry: super::telemetry::Telemetry,
+}
 
-    let mac = bind::get_mac(target_ip)?;
-    let response = client
-        .post(&request_url)
-        .json(&ReportRequest {
-            mac: mac.clone(),
+fn machine_state() -> anyhow::Result<MachineState> {
+    let mac = super::net::server_interface()?.mac;
+    Ok(MachineState {
+        fingerprint: super::fingerprint::machine_fingerprint(&mac),
+        mac,
+        telemetry: super::telemetry::collect(),
+    })
+}
+
+impl MachineState {
+    fn into_request<'a>(
+        self,
+        synced: bool,
+        sync_error: Option<&'a str>,
+        drift: Option<&'a str>,
+        acks: &'a [CommandAck],
+    ) -> ReportRequest<'a> {
+        ReportRequest {
+            mac: self.mac,
+            fingerprint: self.fingerprint,
             synced,
             client_version: version!().to_string(),
-        })
-        .send()?;
+            sync_error,
+            drift,
+            acks,
+            telemetry: self.telemetry,
+        }
+    }
+}
 
-    match response.status() {
+fn report_url() -> String {
+    let base_url = &crate::GLOBAL_CONFIG
+        .get()
+        .expect_or_log("Global config not initialized")
+        .client
+        .server_addr;
+    format!("{}/report", base_url)
+}
+
+fn parse_response(
+    mac: &str,
+    synced: bool,
+    status: StatusCode,
+    body: &[u8],
+) -> anyhow::Result<ReportResponse> {
+    match status {
         StatusCode::OK => {
             tracing::info!("Report MAC {} synced {} successful!", mac, synced);
-            Ok(())
+            // Older servers answer with an empty body
+            if body.is_empty() {
+                return Ok(ReportResponse::default());
+            }
+            Ok(serde_json::from_slice(body)?)
         }
         other => {
-            let error: crate::client::ErrorResponse = response.json()?;
+            let error: crate::client::ErrorResponse = serde_json::from_slice(body)?;
             bail!(
                 "Wrong response code {}, error {} {}",
                 other,
@@ -59,21 +113,259 @@ pub fn send_report(synced: bool) -> anyhow::Result<()> {
     }
 }
 
+fn post_report(
+    synced: bool,
+    sync_error: Option<&str>,
+    acks: &[CommandAck],
+) -> anyhow::Result<ReportResponse> {
+    let client = super::build_server_http_client()?;
+    let state = machine_state()?;
+    let mac = state.mac.clone();
+    let response = client
+        .post(report_url())
+        .json(&state.into_request(synced, sync_error, None, acks))
+        .send()?;
+    let status = response.status();
+    parse_response(&mac, synced, status, &response.bytes()?)
+}
+
+/// Send a sync outcome right away, queueing it in the outbox for the monitor if the server
+/// can't be reached so it isn't lost
+fn deliver_or_queue(synced: bool, sync_error: Option<&str>) -> anyhow::Result<()> {
+    if let Err(err) = post_report(synced, sync_error, &[]) {
+        tracing::warn!(
+            "Failed to report, queueing for the monitor service: {:#}",
+            err
+        );
+        super::outbox::push(synced, sync_error)?;
+    }
+    Ok(())
+}
+
+pub fn send_report(synced: bool) -> anyhow::Result<()> {
+    // Commands are only picked up by the monitor loop, which is the one acknowledging them
+    deliver_or_queue(synced, None)
+}
+
+/// Tell the server a sync was rolled back so the seat shows up in panel
+pub fn report_sync_failure(reason: &str) -> anyhow::Result<()> {
+    deliver_or_queue(false, Some(reason))
+}
+
+/// Async client kept across heartbeats, rebuilt once bind replaces the client certificate
+struct Reporter {
+    client: reqwest::Client,
+    cert_modified: Option<SystemTime>,
+}
+
+fn client_cert_modified() -> Option<SystemTime> {
+    let cert_path = &crate::GLOBAL_CONFIG
+        .get()
+        .expect_or_log("Global config not initialized")
+        .client
+        .tls_client_cert_path;
+    std::fs::metadata(cert_path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+impl Reporter {
+    fn new() -> anyhow::Result<Self> {
+        Ok(Reporter {
+            client: super::build_async_server_http_client()?,
+            cert_modified: client_cert_modified(),
+        })
+    }
+
+    fn refresh(&mut self) -> anyhow::Result<()> {
+        let cert_modified = client_cert_modified();
+        if cert_modified != self.cert_modified {
+            tracing::info!("Client certificate changed, rebuilding HTTPS client");
+            self.client = super::build_async_server_http_client()?;
+            self.cert_modified = cert_modified;
+        }
+        Ok(())
+    }
+
+    async fn post(
+        &mut self,
+        synced: bool,
+        sync_error: Option<&str>,
+        drift: Option<&str>,
+        acks: &[CommandAck],
+    ) -> anyhow::Result<ReportResponse> {
+        self.refresh()?;
+        let state = tokio::task::spawn_blocking(machine_state).await??;
+        let mac = state.mac.clone();
+        let response = self
+            .client
+            .post(report_url())
+            .json(&state.into_request(synced, sync_error, drift, acks))
+            .send()
+            .await?;
+        let status = response.status();
+        parse_response(&mac, synced, status, &response.bytes().await?)
+    }
+
+    /// Deliver queued reports oldest first, stopping at the first failure to keep their order
+    async fn flush_outbox(&mut self) -> anyhow::Result<()> {
+        while let Some(queued) = super::outbox::peek()? {
+            tracing::info!(
+                "Delivering report queued at {}, synced {}",
+                queued.queued_at,
+                queued.synced
+            );
+            self.post(queued.synced, queued.sync_error.as_deref(), None, &[])
+                .await?;
+            super::outbox::remove(&queued)?;
+        }
+        Ok(())
+    }
+}
+
+/// Random extra delay spreading clients over the jitter window
+fn jitter(max_secs: u64) -> Duration {
+    Duration::from_millis(rand::rng().random_range(0..=max_secs * 1000))
+}
+
+/// Interval doubling with each failure in a row, capped at the max backoff
+fn backoff(interval_secs: u64, max_backoff_secs: u64, failures: u32) -> Duration {
+    let factor = 1u64.checked_shl(failures).unwrap_or(u64::MAX);
+    Duration::from_secs(
+        interval_secs
+            .saturating_mul(factor)
+            .min(max_backoff_secs.max(interval_secs)),
+    )
+}
+
+fn execute_command(command: &RemoteCommand) -> CommandAck {
+    tracing::info!(
+        "Executing remote command {} ({})",
+        command.id,
+        command.command
+    );
+    let result = match command.command.as_str() {
+        "clean" => super::clean_user(),
+        "sync" => super::sync_info(),
+        "terminate_session" => super::terminate_sessions(),
+        "autologin_session" => super::autologin_session(),
+        "lock_session" => super::lock_sessions(),
+        other => Err(anyhow::Error::msg(format!("Unsupported command {other}"))),
+    };
+
+    match result {
+        Ok(_) => {
+            tracing::info!("Remote command {} succeeded", command.id);
+            CommandAck {
+                id: command.id,
+                success: true,
+                message: String::new(),
+            }
+        }
+        Err(err) => {
+            tracing::error!("Remote command {} failed with error {:#}", command.id, err);
+            CommandAck {
+                id: command.id,
+                success: false,
+                message: format!("{err:#}"),
+            }
+        }
+    }
+}
+
 pub fn do_monitor() -> anyhow::Result<()> {
+    let client_config = &crate::GLOBAL_CONFIG
+        .get()
+        .expect_or_log("Global config not initialized")
+        .client;
+    let interval_secs = client_config.report_interval_secs;
+    let jitter_secs = client_config.report_jitter_secs;
+    let max_backoff_secs = client_config.report_max_backoff_secs;
+
     let runtime = tokio::runtime::Runtime::new()?;
     runtime.block_on(async {
         let forever: tokio::task::JoinHandle<Result<(), anyhow::Error>> =
-            tokio::task::spawn(async {
-                let mut interval = tokio::time::interval(Duration::from_secs(60));
+            tokio::task::spawn(async move {
+                let mut reporter = Reporter::new()?;
+                // Results waiting to be acknowledged on the next heartbeat
+                let mut acks: Vec<CommandAck> = Vec::new();
+                let mut failures: u32 = 0;
+                // Drift reported on the next heartbeat that goes through
+                let mut drift: Option<String> = None;
+                // Drift still there after the last resync attempt
+                let mut unhealed: Option<String> = None;
+                // A healed drift is cleared on the server by a synced heartbeat sent after it
+                let mut resynced = false;
+                // Machines booted together should not send their first report together
+                tokio::time::sleep(jitter(jitter_secs)).await;
                 loop {
-                    interval.tick().await;
-                    let result = send_report(false);
-                    match result {
-                        Ok(_) => {}
-                        Err(err) => {
-                            tracing::error!("Error sending report {:#}", err);
+                    match tokio::task::spawn_blocking(super::drift::detect_drift).await? {
+                        Some(reason) => {
+                            tracing::warn!(
+                                "Caddy drifted from the last sync, resyncing: {}",
+                                reason
+                            );
+                            let healed = match tokio::task::spawn_blocking(super::drift::heal_drift)
+                                .await?
+                            {
+                                Ok(_) => {
+                                    tracing::info!("Caddy restored to the synced config");
+                                    true
+                                }
+                                Err(err) => {
+                                    tracing::error!("Failed to resync drifted Caddy: {:#}", err);
+                                    false
+                                }
+                            };
+                            // A drift resync keeps failing on is reported once, not every tick
+                            if unhealed.as_ref() != Some(&reason) {
+                                drift = Some(reason.clone());
+                            }
+                            unhealed = (!healed).then_some(reason);
+                            resynced = healed;
                         }
+                        None => unhealed = None,
                     }
+
+                    let clear_drift = resynced && drift.is_none();
+                    let result = match reporter.flush_outbox().await {
+                        Ok(_) => {
+                            reporter
+                                .post(clear_drift, None, drift.as_deref(), &acks)
+                                .await
+                        }
+                        Err(err) => Err(err),
+                    };
+                    let delay = match result {
+                        Ok(response) => {
+                            failures = 0;
+                            if clear_drift {
+                                resynced = false;
+                            }
+                            drift = None;
+                            acks.clear();
+                            for command in response.commands {
+                                // Commands call the blocking client and shell out
+                                acks.push(
+                                    tokio::task::spawn_blocking(move || execute_command(&command))
+                                        .await?,
+                                );
+                            }
+                            Duration::from_secs(interval_secs)
+                        }
+                        Err(err) => {
+                            failures = failures.saturating_add(1);
+                            let delay = backoff(interval_secs, max_backoff_secs, failures);
+                            tracing::error!(
+                                "Error sending report, attempt {} failed, retrying in {}s: {:#}",
+                                failures,
+                                delay.as_secs(),
+                                err
+                            );
+                            delay
+                        }
+                    };
+                    tokio::time::sleep(delay + jitter(jitter_secs)).await;
                 }
             });
         forever.await??;
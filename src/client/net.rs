@@ -0,0 +1,210 @@
+use std::{
+    fmt::Display,
+    fs::{read_dir, read_to_string},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket},
+    path::Path,
+};
+
+use anyhow::{Context, bail};
+use tracing_unwrap::OptionExt;
+
+const SYS_CLASS_NET: &str = "/sys/class/net";
+/// Reported by interfaces without a real link-layer address, like loopback or some tunnels
+const EMPTY_MAC: &str = "00:00:00:00:00:00";
+
+/// How the outgoing interface relates to the hardware, decides which address identifies the machine
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InterfaceKind {
+    /// Backed by a physical device
+    Physical,
+    /// Bonding master, keeps its own address like the `ip link` output older versions bound by
+    Bond { slaves: Vec<String> },
+    /// 802.1Q VLAN, identified by the interface it is stacked on
+    Vlan { lower: String },
+    /// Linux bridge, keeps its own address
+    Bridge,
+    /// Any other software interface with a link-layer address, e.g. veth or macvlan
+    Virtual,
+}
+
+/// Network interface the kernel routes server traffic through
+#[derive(Debug, Clone)]
+pub struct NetInterface {
+    pub name: String,
+    /// Source address used towards the server
+    pub ip: IpAddr,
+    /// Address the server knows this machine by
+    pub mac: String,
+    pub kind: InterfaceKind,
+}
+
+impl Display for NetInterface {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} on {} ({:?}) from {}",
+            self.mac, self.name, self.kind, self.ip
+        )
+    }
+}
+
+fn read_attribute(interface: &str, attribute: &str) -> anyhow::Result<String> {
+    let path = Path::new(SYS_CLASS_NET).join(interface).join(attribute);
+    Ok(read_to_string(&path)
+        .with_context(|| format!("Failed to read {}", path.display()))?
+        .trim()
+        .to_string())
+}
+
+fn has_attribute(interface: &str, attribute: &str) -> bool {
+    Path::new(SYS_CLASS_NET)
+        .join(interface)
+        .join(attribute)
+        .exists()
+}
+
+fn detect_kind(interface: &str) -> anyhow::Result<InterfaceKind> {
+    if has_attribute(interface, "bonding") {
+        let mut slaves = read_attribute(interface, "bonding/slaves")?
+            .split_whitespace()
+            .map(str::to_string)
+            .collect::<Vec<String>>();
+        slaves.sort();
+        return Ok(InterfaceKind::Bond { slaves });
+    }
+    if has_attribute(interface, "bridge") {
+        return Ok(InterfaceKind::Bridge);
+    }
+    // Stacked interfaces link their lower device as `lower_<name>`
+    let uevent = read_attribute(interface, "uevent").unwrap_or_default();
+    if uevent.lines().any(|line| line == "DEVTYPE=vlan") {
+        let lower = read_dir(Path::new(SYS_CLASS_NET).join(interface))?
+            .filter_map(|entry| entry.ok())
+            .find_map(|entry| {
+                entry
+                    .file_name()
+                    .to_string_lossy()
+                    .strip_prefix("lower_")
+                    .map(str::to_string)
+            })
+            .with_context(|| format!("VLAN {} has no lower interface", interface))?;
+        return Ok(InterfaceKind::Vlan { lower });
+    }
+    if has_attribute(interface, "device") {
+        return Ok(InterfaceKind::Physical);
+    }
+    Ok(InterfaceKind::Virtual)
+}
+
+/// Link-layer address identifying the machine behind an interface, following VLANs down
+fn identity_mac(interface: &str, kind: &InterfaceKind) -> anyhow::Result<String> {
+    let mac = match kind {
+        InterfaceKind::Vlan { lower } => {
+            let lower_kind = detect_kind(lower)?;
+            tracing::debug!(
+                "VLAN {} is stacked on {} ({:?})",
+                interface,
+                lower,
+                lower_kind
+            );
+            identity_mac(lower, &lower_kind)?
+        }
+        InterfaceKind::Physical
+        | InterfaceKind::Bond { .. }
+        | InterfaceKind::Bridge
+        | InterfaceKind::Virtual => read_attribute(interface, "address")?,
+    };
+    if mac.is_empty() || mac == EMPTY_MAC {
+        bail!(
+            "Interface {} has no usable link-layer address '{}', the route to the server likely goes through a tunnel",
+            interface,
+            mac
+        );
+    }
+    Ok(mac)
+}
+
+/// Source address the kernel picks for the target, connecting a UDP socket sends no packet
+fn route_source(target: SocketAddr) -> anyhow::Result<IpAddr> {
+    let bind_addr: SocketAddr = match target {
+        SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    };
+    let socket = UdpSocket::bind(bind_addr)?;
+    socket
+        .connect(target)
+        .with_context(|| format!("No route to {}", target))?;
+    Ok(socket.local_addr()?.ip())
+}
+
+/// Resolve the interface used to reach the target
+pub fn route_interface(target: SocketAddr) -> anyhow::Result<NetInterface> {
+    if target.ip().is_loopback() {
+        bail!("Can't get MAC of loop addr {}", target.ip());
+    }
+
+    let ip = route_source(target)?;
+    let mut names = if_addrs::get_if_addrs()?
+        .into_iter()
+        .filter(|interface| interface.ip() == ip)
+        .map(|interface| interface.name)
+        .collect::<Vec<String>>();
+    names.sort();
+    names.dedup();
+    let name = match names.as_slice() {
+        [name] => name.clone(),
+        [] => bail!(
+            "No interface holds source address {} towards {}",
+            ip,
+            target
+        ),
+        _ => bail!(
+            "Source address {} towards {} is assigned to several interfaces: {}",
+            ip,
+            target,
+            names.join(", ")
+        ),
+    };
+
+    let kind = detect_kind(&name)?;
+    let mac = identity_mac(&name, &kind)?;
+    tracing::debug!(
+        "Server {} is reached through {} ({:?}) from {} with MAC {}",
+        target,
+        name,
+        kind,
+        ip,
+        mac
+    );
+    Ok(NetInterface {
+        name,
+        ip,
+        mac,
+        kind,
+    })
+}
+
+/// Interface used to reach the configured server
+pub fn server_interface() -> anyhow::Result<NetInterface> {
+    let base_url = &crate::GLOBAL_CONFIG
+        .get()
+        .expect_or_log("Global config not initialized")
+        .client
+        .server_addr;
+    let parsed_url = reqwest::Url::parse(base_url)
+        .map_err(|_| anyhow::Error::msg("Failed to parse base URL"))?;
+    let target = parsed_url
+        .socket_addrs(|| None)
+        .with_context(|| format!("Failed to resolve server address {}", base_url))?
+        .into_iter()
+        .next()
+        .with_context(|| format!("Server address {} resolved to nothing", base_url))?;
+    route_interface(target)
+}
+
+/// Whether any local interface holds the address, tells a direct connection from a NAT
+pub fn has_local_address(ip: IpAddr) -> anyhow::Result<bool> {
+    Ok(if_addrs::get_if_addrs()?
+        .iter()
+        .any(|interface| interface.ip() == ip))
+}
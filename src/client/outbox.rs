@@ -0,0 +1,100 @@
+use std::{
+    fs::read,
+    path::{Path, PathBuf},
+};
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use tracing_unwrap::OptionExt;
+
+use super::fsutil::{lock_beside, write_atomic};
+
+/// Oldest reports are dropped beyond this, only the latest sync outcome matters to the server
+const MAX_QUEUED_REPORTS: usize = 32;
+
+/// Report the server has to see eventually, plain heartbeats are never queued
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct QueuedReport {
+    pub synced: bool,
+    pub sync_error: Option<String>,
+    pub queued_at: i64,
+}
+
+fn outbox_path() -> PathBuf {
+    PathBuf::from(
+        &crate::GLOBAL_CONFIG
+            .get()
+            .expect_or_log("Global config not initialized")
+            .client
+            .outbox_path,
+    )
+}
+
+fn load(path: &Path) -> Vec<QueuedReport> {
+    let content = match read(path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Vec::new(),
+        Err(err) => {
+            tracing::error!("Failed to read outbox {}, err {}", path.display(), err);
+            return Vec::new();
+        }
+    };
+    serde_json::from_slice(&content).unwrap_or_else(|err| {
+        tracing::error!(
+            "Outbox {} is corrupted, dropping queued reports, err {}",
+            path.display(),
+            err
+        );
+        Vec::new()
+    })
+}
+
+/// Keep a report for delivery once the server is reachable again
+pub fn push(synced: bool, sync_error: Option<&str>) -> anyhow::Result<()> {
+    let path = outbox_path();
+    let _lock = lock_beside(&path)?;
+    let mut reports = load(&path);
+    reports.push(QueuedReport {
+        synced,
+        sync_error: sync_error.map(str::to_string),
+        queued_at: Utc::now().timestamp(),
+    });
+    if reports.len() > MAX_QUEUED_REPORTS {
+        let dropped = reports.len() - MAX_QUEUED_REPORTS;
+        tracing::warn!("Outbox full, dropping {} oldest reports", dropped);
+        reports.drain(..dropped);
+    }
+    write_atomic(&path, &serde_json::to_vec(&reports)?)?;
+    tracing::info!(
+        "Report queued in outbox {}, {} waiting for delivery",
+        path.display(),
+        reports.len()
+    );
+    Ok(())
+}
+
+/// Number of reports waiting for delivery
+pub fn queued_count() -> anyhow::Result<usize> {
+    let path = outbox_path();
+    let _lock = lock_beside(&path)?;
+    Ok(load(&path).len())
+}
+
+/// Oldest report waiting for delivery
+pub fn peek() -> anyhow::Result<Option<QueuedReport>> {
+    let path = outbox_path();
+    let _lock = lock_beside(&path)?;
+    Ok(load(&path).into_iter().next())
+}
+
+/// Drop a delivered report, unless another process already rewrote the outbox head
+pub fn remove(delivered: &QueuedReport) -> anyhow::Result<()> {
+    let path = outbox_path();
+    let _lock = lock_beside(&path)?;
+    let mut reports = load(&path);
+    if reports.first() == Some(delivered) {
+        reports.remove(0);
+        write_atomic(&path, &serde_json::to_vec(&reports)?)?;
+    }
+    Ok(())
+}
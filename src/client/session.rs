@@ -7,7 +7,15 @@ use std::{
 use anyhow::bail;
 use tracing_unwrap::{OptionExt, ResultExt};
 
+use super::state::{self, StateAction};
+
 pub fn terminate_sessions() -> anyhow::Result<()> {
+    let result = terminate_player_session();
+    state::record(StateAction::TerminateSession, &result);
+    result
+}
+
+fn terminate_player_session() -> anyhow::Result<()> {
     let lightdm_config = "/etc/lightdm/lightdm.conf";
     let username = crate::GLOBAL_CONFIG
         .get()
@@ -72,7 +80,70 @@ pub fn terminate_sessions() -> anyhow::Result<()> {
     }
 }
 
+pub fn lock_sessions() -> anyhow::Result<()> {
+    let result = lock_player_sessions();
+    state::record(StateAction::LockSession, &result);
+    result
+}
+
+/// Ask logind to lock every session of the player, the screen locker of the desktop does the rest
+fn lock_player_sessions() -> anyhow::Result<()> {
+    let username = crate::GLOBAL_CONFIG
+        .get()
+        .expect_or_log("Global config not initialized")
+        .client
+        .player_user
+        .clone();
+
+    let output = Command::new("loginctl")
+        .arg("list-sessions")
+        .arg("--no-legend")
+        .output()
+        .expect_or_log("Failed to list sessions");
+    if !output.status.success() {
+        bail!(
+            "Failed to list sessions: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    // Columns are SESSION UID USER SEAT TTY
+    let sessions: Vec<String> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let columns: Vec<&str> = line.split_whitespace().collect();
+            (columns.get(2) == Some(&username.as_str())).then(|| columns[0].to_string())
+        })
+        .collect();
+
+    if sessions.is_empty() {
+        tracing::info!(
+            "User {} is not currently logged in. Skipping lock.",
+            username
+        );
+        return Ok(());
+    }
+
+    for session in sessions {
+        let status = Command::new("loginctl")
+            .arg("lock-session")
+            .arg(&session)
+            .status()
+            .expect_or_log("Failed to lock user session");
+        if !status.success() {
+            bail!("Failed to lock session {} of {}", session, username);
+        }
+        tracing::info!("Session {} of {} locked", session, username);
+    }
+    Ok(())
+}
+
 pub fn autologin_session() -> anyhow::Result<()> {
+    let result = enable_autologin();
+    state::record(StateAction::AutologinSession, &result);
+    result
+}
+
+fn enable_autologin() -> anyhow::Result<()> {
     let lightdm_config = "/etc/lightdm/lightdm.conf";
     let username = crate::GLOBAL_CONFIG
         .get()
@@ -0,0 +1,157 @@
+use std::{fs::read, path::Path};
+
+use anyhow::Context;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use tracing_unwrap::OptionExt;
+
+use super::fsutil::{lock_beside, write_atomic};
+
+/// Oldest events are dropped beyond this
+const MAX_HISTORY: usize = 50;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StateAction {
+    Bind,
+    Sync,
+    Clean,
+    TerminateSession,
+    AutologinSession,
+    LockSession,
+}
+
+impl StateAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StateAction::Bind => "bind",
+            StateAction::Sync => "sync",
+            StateAction::Clean => "clean",
+            StateAction::TerminateSession => "terminate_session",
+            StateAction::AutologinSession => "autologin_session",
+            StateAction::LockSession => "lock_session",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct StateEvent {
+    pub at: i64,
+    pub action: StateAction,
+    pub success: bool,
+    /// Bound ID on bind, error otherwise
+    #[serde(default)]
+    pub detail: Option<String>,
+}
+
+/// What this machine did last, kept locally so it can be told without asking the server
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct ClientState {
+    #[serde(default)]
+    pub bound_id: Option<String>,
+    #[serde(default)]
+    pub bound_at: Option<i64>,
+    /// Last successful sync
+    #[serde(default)]
+    pub last_sync_at: Option<i64>,
+    /// Reason the latest sync failed, cleared by the next successful one
+    #[serde(default)]
+    pub last_sync_error: Option<String>,
+    #[serde(default)]
+    pub last_clean_at: Option<i64>,
+    /// Latest events, oldest first
+    #[serde(default)]
+    pub history: Vec<StateEvent>,
+}
+
+fn state_path() -> String {
+    crate::GLOBAL_CONFIG
+        .get()
+        .expect_or_log("Global config not initialized")
+        .client
+        .state_path
+        .clone()
+}
+
+fn load_from(path: &Path) -> anyhow::Result<ClientState> {
+    match read(path) {
+        Ok(content) => serde_json::from_slice(&content)
+            .with_context(|| format!("State file {} is corrupted", path.display())),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(ClientState::default()),
+        Err(err) => Err(err).with_context(|| format!("Failed to read {}", path.display())),
+    }
+}
+
+pub fn load() -> anyhow::Result<ClientState> {
+    let path = state_path();
+    let path = Path::new(&path);
+    let _lock = lock_beside(path)?;
+    load_from(path)
+}
+
+fn update(apply: impl FnOnce(&mut ClientState)) -> anyhow::Result<()> {
+    let path = state_path();
+    let path = Path::new(&path);
+    let _lock = lock_beside(path)?;
+    let mut state = load_from(path).unwrap_or_else(|err| {
+        tracing::error!("{:#}, starting a new one", err);
+        ClientState::default()
+    });
+    apply(&mut state);
+    if state.history.len() > MAX_HISTORY {
+        let dropped = state.history.len() - MAX_HISTORY;
+        state.history.drain(..dropped);
+    }
+    write_atomic(path, &serde_json::to_vec_pretty(&state)?)
+}
+
+fn log_failure(action: StateAction, result: anyhow::Result<()>) {
+    if let Err(err) = result {
+        tracing::warn!(
+            "Failed to record {} in the state file: {:#}",
+            action.as_str(),
+            err
+        );
+    }
+}
+
+/// Record the ID the server bound this machine to
+pub fn record_bound(id: Option<&str>) {
+    let result = update(|state| {
+        let now = Utc::now().timestamp();
+        if id.is_some() {
+            state.bound_id = id.map(str::to_string);
+        }
+        state.bound_at = Some(now);
+        state.history.push(StateEvent {
+            at: now,
+            action: StateAction::Bind,
+            success: true,
+            detail: id.map(str::to_string),
+        });
+    });
+    log_failure(StateAction::Bind, result);
+}
+
+/// Record the outcome of an operation, successful binds are recorded by [`record_bound`]
+pub fn record(action: StateAction, result: &anyhow::Result<()>) {
+    let update_result = update(|state| {
+        let now = Utc::now().timestamp();
+        match (action, result) {
+            (StateAction::Sync, Ok(_)) => {
+                state.last_sync_at = Some(now);
+                state.last_sync_error = None;
+            }
+            (StateAction::Sync, Err(err)) => state.last_sync_error = Some(format!("{err:#}")),
+            (StateAction::Clean, Ok(_)) => state.last_clean_at = Some(now),
+            _ => {}
+        }
+        state.history.push(StateEvent {
+            at: now,
+            action,
+            success: result.is_ok(),
+            detail: result.as_ref().err().map(|err| format!("{err:#}")),
+        });
+    });
+    log_failure(action, update_result);
+}
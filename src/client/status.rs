@@ -0,0 +1,97 @@
+use std::time::Instant;
+
+use chrono::{DateTime, Local};
+use tracing_unwrap::OptionExt;
+
+/// Recent events shown by `status`
+const SHOWN_HISTORY: usize = 5;
+
+fn format_time(timestamp: Option<i64>) -> String {
+    timestamp
+        .and_then(|timestamp| DateTime::from_timestamp(timestamp, 0))
+        .map(|time| {
+            time.with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M:%S")
+                .to_string()
+        })
+        .unwrap_or_else(|| "never".to_string())
+}
+
+/// Any HTTP answer counts, the TLS handshake already proves it is our server
+fn check_server() -> String {
+    let base_url = &crate::GLOBAL_CONFIG
+        .get()
+        .expect_or_log("Global config not initialized")
+        .client
+        .server_addr;
+    let client = match super::build_server_http_client() {
+        Ok(client) => client,
+        Err(err) => return format!("unknown, {:#}", err),
+    };
+    let start = Instant::now();
+    match client.get(base_url).send() {
+        Ok(_) => format!(
+            "reachable at {} in {} ms",
+            base_url,
+            start.elapsed().as_millis()
+        ),
+        Err(err) => format!("unreachable at {}, {:#}", base_url, err),
+    }
+}
+
+/// Summary for proctors at the seat, read from the local state and live checks
+pub fn print_status() -> anyhow::Result<()> {
+    let state = super::state::load()?;
+
+    tracing::info!(
+        "Bound ID: {}",
+        state.bound_id.as_deref().unwrap_or("not bound")
+    );
+    tracing::info!("Bound at: {}", format_time(state.bound_at));
+    match super::net::server_interface() {
+        Ok(interface) => tracing::info!("Interface: {}", interface),
+        Err(err) => tracing::info!("Interface: unknown, {:#}", err),
+    }
+    tracing::info!("Last sync: {}", format_time(state.last_sync_at));
+    if let Some(error) = &state.last_sync_error {
+        tracing::info!("Last sync failed: {}", error);
+    }
+    tracing::info!("Last clean: {}", format_time(state.last_clean_at));
+    tracing::info!("Server: {}", check_server());
+    tracing::info!(
+        "Caddy: {}",
+        if super::check::check_caddy_active() {
+            "active"
+        } else {
+            "not active"
+        }
+    );
+    if state.last_sync_at.is_none() {
+        tracing::info!("Caddy config: not synced yet");
+    } else if let Some(drift) = super::drift::detect_drift() {
+        tracing::info!("Caddy config: drifted, {}", drift);
+    } else {
+        tracing::info!("Caddy config: matches last sync");
+    }
+    match super::outbox::queued_count() {
+        Ok(0) => {}
+        Ok(count) => tracing::info!("Reports waiting for delivery: {}", count),
+        Err(err) => tracing::info!("Reports waiting for delivery: unknown, {:#}", err),
+    }
+
+    let skipped = state.history.len().saturating_sub(SHOWN_HISTORY);
+    for event in &state.history[skipped..] {
+        tracing::info!(
+            "{} {} {}{}",
+            format_time(Some(event.at)),
+            event.action.as_str(),
+            if event.success { "ok" } else { "failed" },
+            event
+                .detail
+                .as_deref()
+                .map(|detail| format!(", {}", detail))
+                .unwrap_or_default()
+        );
+    }
+    Ok(())
+}
@@ -1,4 +1,10 @@
-use std::{fs::write, process::Command};
+use std::{
+    fs::{File, OpenOptions, hard_link, read_to_string, remove_file, rename},
+    io::Write,
+    os::unix::fs::OpenOptionsExt,
+    path::Path,
+    process::Command,
+};
 
 use anyhow::bail;
 use base64::{Engine, prelude::BASE64_STANDARD};
@@ -6,11 +12,15 @@ use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
 use tracing_unwrap::OptionExt;
 
-use super::monitor;
+use super::{
+    caddy, drift, monitor,
+    state::{self, StateAction},
+};
 
 #[derive(Serialize)]
 struct SyncRequestBody {
     mac: String,
+    fingerprint: String,
 }
 
 #[derive(Deserialize, Debug)]
@@ -25,14 +35,9 @@ fn fetch_info() -> anyhow::Result<SyncResponseBody> {
         .unwrap_or_log()
         .client
         .server_addr;
-    let parsed_url = reqwest::Url::parse(base_url)
-        .map_err(|_| anyhow::Error::msg("Failed to parse base URL"))?;
-    let target_ip = parsed_url
-        .host_str()
-        .expect_or_log("Failed to get host str from base URL")
-        .to_string();
-    let mac = super::bind::get_mac(target_ip)?;
-    tracing::info!("Current device MAC is {}", mac);
+    let interface = super::net::server_interface()?;
+    tracing::info!("Current device MAC is {}", interface);
+    let mac = interface.mac;
 
     let token = crate::GLOBAL_CONFIG
         .get()
@@ -44,7 +49,10 @@ fn fetch_info() -> anyhow::Result<SyncResponseBody> {
     let request_url = format!("{}/sync", base_url);
     let client = super::build_server_http_client()?;
 
-    let request_body = SyncRequestBody { mac };
+    let request_body = SyncRequestBody {
+        fingerprint: super::fingerprint::machine_fingerprint(&mac),
+        mac,
+    };
 
     let response = client
         .post(request_url)
@@ -68,64 +76,63 @@ fn fetch_info() -> anyhow::Result<SyncResponseBody> {
     Ok(info)
 }
 
-fn format_caddyfile(username: String, password: String) -> String {
-    let domjudge_addr = crate::GLOBAL_CONFIG
-        .get()
-        .expect_or_log("Global config not initialized")
-        .client
-        .domjudge_addr
-        .clone();
-    let reverse_addr = crate::GLOBAL_CONFIG
-        .get()
-        .expect_or_log("Global config not initialized")
-        .client
-        .reverse_addr
-        .clone();
-    let tls_reverse_cert_path = crate::GLOBAL_CONFIG
-        .get()
-        .expect_or_log("Global config not initialized")
-        .client
-        .tls_reverse_cert_path
-        .clone();
-    let tls_reverse_key_path = crate::GLOBAL_CONFIG
-        .get()
-        .expect_or_log("Global config not initialized")
-        .client
-        .tls_reverse_key_path
-        .clone();
-    let mut encoded_password = String::new();
-    BASE64_STANDARD.encode_string(password, &mut encoded_password);
-    format!(
-        r#"
-{{
-    admin localhost:20190
+const DEFAULT_CADDYFILE_TEMPLATE: &str = r#"
+{
+    admin {{caddy_admin_addr}}
 	auto_https off
-}}
+}
 
-{}:443 {{
-    tls {} {}
+{{reverse_addr}}:443 {
+    tls {{tls_reverse_cert_path}} {{tls_reverse_key_path}}
 	@autologin path /login*
 
-	handle @autologin {{
-		reverse_proxy {} {{
-			header_up X-DOMjudge-Login "{}"
-			header_up X-DOMjudge-Pass "{}"
-        }}
-	}}
-
-	handle {{
-		reverse_proxy {}
-	}}
-}}
-
-    "#,
-        reverse_addr,
-        tls_reverse_cert_path,
-        tls_reverse_key_path,
-        domjudge_addr,
-        username,
-        encoded_password,
-        domjudge_addr
+	handle @autologin {
+		reverse_proxy {{domjudge_addr}} {
+			header_up X-DOMjudge-Login "{{username}}"
+			header_up X-DOMjudge-Pass "{{password_base64}}"
+        }
+	}
+
+	handle {
+		reverse_proxy {{domjudge_addr}}
+	}
+}
+
+    "#;
+
+fn format_caddyfile(username: String, password: String) -> anyhow::Result<String> {
+    let client_config = &crate::GLOBAL_CONFIG
+        .get()
+        .expect_or_log("Global config not initialized")
+        .client;
+    let template = match &client_config.caddyfile_template {
+        Some(template_path) => match read_to_string(template_path) {
+            Ok(template) => template,
+            Err(err) => bail!(
+                "Failed to read Caddyfile template {}, err {}",
+                template_path,
+                err
+            ),
+        },
+        None => DEFAULT_CADDYFILE_TEMPLATE.to_string(),
+    };
+    let mut encoded_password = String::new();
+    BASE64_STANDARD.encode_string(&password, &mut encoded_password);
+    caddy::render_template(
+        &template,
+        &[
+            ("username", &username),
+            ("password", &password),
+            ("password_base64", &encoded_password),
+            ("domjudge_addr", &client_config.domjudge_addr),
+            ("reverse_addr", &client_config.reverse_addr),
+            (
+                "tls_reverse_cert_path",
+                &client_config.tls_reverse_cert_path,
+            ),
+            ("tls_reverse_key_path", &client_config.tls_reverse_key_path),
+            ("caddy_admin_addr", &client_config.caddy_admin_addr),
+        ],
     )
 }
 
@@ -156,14 +163,115 @@ fn reset_caddyfile_permission(path: String) -> bool {
     }
 }
 
+/// Atomically swap in the new Caddyfile, keeping the previous one as `<caddyfile>.bak`
+fn replace_caddyfile(caddyfile_path: &str, caddyfile: &str) -> anyhow::Result<()> {
+    let temp_path = format!("{caddyfile_path}.tmp");
+    let backup_path = format!("{caddyfile_path}.bak");
+
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(&temp_path)
+        .map_err(|err| anyhow::Error::msg(format!("Failed to create {temp_path}, err {err}")))?;
+    file.write_all(caddyfile.as_bytes())?;
+    file.sync_all()?;
+    drop(file);
+
+    if !reset_caddyfile_permission(temp_path.clone()) {
+        bail!("Failed to reset Caddyfile permission!")
+    }
+
+    // A hard link keeps the old file itself, with its caddy ownership and mode, for rollback
+    let _ = remove_file(&backup_path);
+    if Path::new(caddyfile_path).exists()
+        && let Err(err) = hard_link(caddyfile_path, &backup_path)
+    {
+        bail!(
+            "Failed to back up Caddyfile to {}, err {}",
+            backup_path,
+            err
+        )
+    }
+
+    if let Err(err) = rename(&temp_path, caddyfile_path) {
+        bail!("Failed to move {} to Caddyfile, err {}", temp_path, err)
+    }
+    sync_parent_dir(caddyfile_path)
+}
+
+/// Move the backup back in place, returning the restored content
+fn restore_caddyfile(caddyfile_path: &str) -> anyhow::Result<String> {
+    let backup_path = format!("{caddyfile_path}.bak");
+    let previous = match read_to_string(&backup_path) {
+        Ok(previous) => previous,
+        Err(err) => bail!(
+            "No Caddyfile backup {} to restore, err {}",
+            backup_path,
+            err
+        ),
+    };
+    if let Err(err) = rename(&backup_path, caddyfile_path) {
+        bail!(
+            "Failed to restore Caddyfile from {}, err {}",
+            backup_path,
+            err
+        )
+    }
+    sync_parent_dir(caddyfile_path)?;
+    Ok(previous)
+}
+
+fn sync_parent_dir(path: &str) -> anyhow::Result<()> {
+    if let Some(parent) = Path::new(path).parent()
+        && !parent.as_os_str().is_empty()
+    {
+        File::open(parent)?.sync_all()?;
+    }
+    Ok(())
+}
+
+/// Make Caddy pick up the config already written to the Caddyfile
+fn apply_caddyfile(caddyfile: &str) -> anyhow::Result<()> {
+    let caddy_admin_api = crate::GLOBAL_CONFIG
+        .get()
+        .expect_or_log("Global config not initialized")
+        .client
+        .caddy_admin_api;
+
+    if caddy_admin_api {
+        match caddy::load_caddyfile(caddyfile) {
+            Ok(_) => return Ok(()),
+            Err(err) => {
+                tracing::warn!(
+                    "Failed to load config through Caddy admin API, falling back to reload: {:#}",
+                    err
+                );
+            }
+        }
+    }
+
+    if !reload_caddy_service() {
+        bail!("Failed to reload caddy service!")
+    }
+    Ok(())
+}
+
 pub fn sync_info() -> anyhow::Result<()> {
+    let result = apply_sync();
+    state::record(StateAction::Sync, &result);
+    result
+}
+
+fn apply_sync() -> anyhow::Result<()> {
     if !crate::client::check::check_caddy_active() {
         return Err(anyhow::Error::msg("Caddy service not running!"));
     }
 
     let info = fetch_info()?;
     // Write caddy file into /etc/caddy/Caddyfile
-    let formated_caddyfile = format_caddyfile(info.username, info.password);
+    let formated_caddyfile = format_caddyfile(info.username, info.password)?;
     let caddyfile_path = crate::GLOBAL_CONFIG
         .get()
         .expect_or_log("Global config not initialized")
@@ -171,31 +279,30 @@ pub fn sync_info() -> anyhow::Result<()> {
         .caddyfile
         .clone();
 
-    // std::fs::remove_file(&caddyfile_path)?;
+    // Never replace the live config with one Caddy would refuse
+    caddy::validate_caddyfile(&formated_caddyfile, &caddyfile_path)?;
 
-    if let Err(err) = write(&caddyfile_path, formated_caddyfile) {
-        bail!("Failed to write to Caddyfile, err {}", err)
-    }
+    replace_caddyfile(&caddyfile_path, &formated_caddyfile)?;
 
-    // match OpenOptions::new()
-    //     .write(true)
-    //     .create(true)
-    //     .open(&caddyfile_path)
-    // {
-    //     Ok(mut file) => {
-    //         file.write_all(formated_caddyfile.as_bytes())?;
-    //     }
-    //     Err(e) => {
-    //         bail!("Failed to write to Caddyfile, err {}", e)
-    //     }
-    // }
-
-    if !reset_caddyfile_permission(caddyfile_path) {
-        bail!("Failed to reset Caddyfile permission!")
+    if let Err(err) =
+        apply_caddyfile(&formated_caddyfile).and_then(|_| caddy::probe_reverse_proxy())
+    {
+        let reason = format!("{err:#}");
+        tracing::error!("Synced Caddyfile failed, rolling back: {}", reason);
+
+        match restore_caddyfile(&caddyfile_path).and_then(|previous| apply_caddyfile(&previous)) {
+            Ok(_) => tracing::info!("Previous Caddyfile restored"),
+            Err(err) => tracing::error!("Failed to restore previous Caddyfile: {:#}", err),
+        }
+
+        if let Err(err) = monitor::report_sync_failure(&reason) {
+            tracing::error!("Failed to report sync failure: {:#}", err);
+        }
+        bail!("Sync rolled back: {}", reason)
     }
 
-    if !reload_caddy_service() {
-        bail!("Failed to reload caddy service!")
+    if let Err(err) = drift::record_synced_caddyfile(&formated_caddyfile) {
+        tracing::error!("Failed to record synced Caddyfile hash: {:#}", err);
     }
 
     monitor::send_report(true)
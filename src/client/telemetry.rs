@@ -0,0 +1,87 @@
+use std::{fs::read_to_string, process::Command};
+
+use anyhow::{Context, bail};
+use serde::Serialize;
+use tracing_unwrap::OptionExt;
+
+/// Health snapshot sent along every heartbeat, a field is left empty when it can't be collected
+#[derive(Serialize)]
+pub struct Telemetry {
+    caddy_active: bool,
+    graphical_session: bool,
+    uptime_secs: Option<i64>,
+    load_avg: Option<f64>,
+    home_free_bytes: Option<i64>,
+    /// Client wall clock in milliseconds, the server derives the clock offset from it
+    timestamp_ms: i64,
+    hostname: Option<String>,
+}
+
+fn read_uptime() -> anyhow::Result<i64> {
+    let uptime = read_to_string("/proc/uptime")?;
+    let seconds = uptime
+        .split_whitespace()
+        .next()
+        .context("Empty /proc/uptime")?
+        .parse::<f64>()?;
+    Ok(seconds as i64)
+}
+
+fn read_load_avg() -> anyhow::Result<f64> {
+    let loadavg = read_to_string("/proc/loadavg")?;
+    Ok(loadavg
+        .split_whitespace()
+        .next()
+        .context("Empty /proc/loadavg")?
+        .parse::<f64>()?)
+}
+
+fn read_free_bytes(path: &str) -> anyhow::Result<i64> {
+    let output = Command::new("df")
+        .arg("--output=avail")
+        .arg("-B1")
+        .arg(path)
+        .output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        bail!("df failed: {}", stderr);
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    Ok(stdout
+        .lines()
+        .nth(1)
+        .context("Missing df output")?
+        .trim()
+        .parse::<i64>()?)
+}
+
+fn log_failure<T>(name: &str, result: anyhow::Result<T>) -> Option<T> {
+    match result {
+        Ok(value) => Some(value),
+        Err(err) => {
+            tracing::debug!("Failed to collect {} telemetry: {:#}", name, err);
+            None
+        }
+    }
+}
+
+pub fn collect() -> Telemetry {
+    let player_user = &crate::GLOBAL_CONFIG
+        .get()
+        .expect_or_log("Global config not initialized")
+        .client
+        .player_user;
+
+    let home_free_bytes =
+        super::desktop::lookup_home_dir(player_user).and_then(|home| read_free_bytes(&home));
+
+    Telemetry {
+        caddy_active: super::check::check_caddy_active(),
+        graphical_session: super::desktop::find_graphical_session(player_user).is_ok(),
+        uptime_secs: log_failure("uptime", read_uptime()),
+        load_avg: log_failure("load", read_load_avg()),
+        home_free_bytes: log_failure("disk", home_free_bytes),
+        timestamp_ms: chrono::Utc::now().timestamp_millis(),
+        hostname: log_failure("hostname", super::desktop::get_hostname()),
+    }
+}
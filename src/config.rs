@@ -25,16 +25,122 @@ pub struct ServerConfig {
     pub tls_key_path: String,
     /// Token for sync authorization
     pub token: String,
+    /// Base64 encoded 32-byte key encrypting player passwords at rest, generate one with
+    /// `openssl rand -base64 32` and keep it, the stored passwords cannot be read without it.
+    /// Optional here only so a config from an older version fails with a clear message.
+    #[serde(default)]
+    pub password_key: Option<String>,
+    /// Reject sync and report requests without the client certificate issued at bind,
+    /// disable only while migrating clients bound by an older version
+    #[serde(default = "default_require_client_cert")]
+    pub require_client_cert: bool,
     /// Enable bind service
     pub enable_bind: bool,
     /// Allow bind update
     pub enable_bind_update: bool,
+    /// Rules resolving the ID for `bind --auto` when the seat map has no ID for the client IP,
+    /// tried in order and the first match wins
+    #[serde(default)]
+    pub auto_bind: Vec<AutoBindRule>,
     /// Enable sync service
     pub enable_sync: bool,
     /// Enable static file service
     pub enable_static_file: bool,
     /// Password for panel
     pub panel_token: String,
+    /// Contest phases fired automatically at wall-clock times
+    #[serde(default)]
+    pub timeline: Vec<TimelineStep>,
+    /// Steps overdue by more than this many seconds are recorded as missed instead of fired,
+    /// so restarting the server after a contest does not replay the whole timeline
+    #[serde(default = "default_timeline_grace_secs")]
+    pub timeline_grace_secs: i64,
+    /// Expected client heartbeat interval in seconds
+    #[serde(default = "default_heartbeat_interval_secs")]
+    pub heartbeat_interval_secs: i64,
+    /// Seats missing this many heartbeats in a row are marked offline
+    #[serde(default = "default_offline_missed_heartbeats")]
+    pub offline_missed_heartbeats: i64,
+    /// Heartbeat history older than this many seconds is pruned
+    #[serde(default = "default_heartbeat_retention_secs")]
+    pub heartbeat_retention_secs: i64,
+    /// Online, offline and flapping events older than this many seconds are pruned
+    #[serde(default = "default_seat_event_retention_secs")]
+    pub seat_event_retention_secs: i64,
+    /// Window in seconds over which offline transitions are counted to detect a flapping seat
+    #[serde(default = "default_flap_window_secs")]
+    pub flap_window_secs: i64,
+    /// Seats going offline this many times within the flap window are flagged as flapping
+    #[serde(default = "default_flap_offline_count")]
+    pub flap_offline_count: i64,
+}
+
+#[cfg(feature = "server")]
+#[derive(Deserialize, Debug)]
+pub struct TimelineStep {
+    /// Unique step name, also shown as the seat phase in panel
+    pub name: String,
+    /// RFC 3339 time to fire this step, e.g. 2026-10-18T09:00:00+08:00
+    pub at: String,
+    /// Command queued for every target seat, one of clean, sync, terminate_session,
+    /// autologin_session, lock_session
+    pub command: String,
+    /// Bound IDs to target, all bound seats when empty
+    #[serde(default)]
+    pub ids: Vec<String>,
+}
+
+#[cfg(feature = "server")]
+#[derive(Deserialize, Debug)]
+pub struct AutoBindRule {
+    /// Regex the client hostname must match, e.g. `^team-(\d+)$`
+    #[serde(default)]
+    pub hostname: Option<String>,
+    /// Network the client IP must be in, e.g. 10.1.2.0/24
+    #[serde(default)]
+    pub cidr: Option<String>,
+    /// Resolved ID, captures of the hostname regex can be referenced as `$1` or `${name}`
+    pub id: String,
+}
+
+#[cfg(feature = "server")]
+fn default_require_client_cert() -> bool {
+    true
+}
+
+#[cfg(feature = "server")]
+fn default_timeline_grace_secs() -> i64 {
+    300
+}
+
+#[cfg(feature = "server")]
+fn default_heartbeat_interval_secs() -> i64 {
+    60
+}
+
+#[cfg(feature = "server")]
+fn default_offline_missed_heartbeats() -> i64 {
+    3
+}
+
+#[cfg(feature = "server")]
+fn default_heartbeat_retention_secs() -> i64 {
+    2 * 24 * 60 * 60
+}
+
+#[cfg(feature = "server")]
+fn default_seat_event_retention_secs() -> i64 {
+    7 * 24 * 60 * 60
+}
+
+#[cfg(feature = "server")]
+fn default_flap_window_secs() -> i64 {
+    30 * 60
+}
+
+#[cfg(feature = "server")]
+fn default_flap_offline_count() -> i64 {
+    3
 }
 
 #[cfg(feature = "server")]
@@ -57,6 +163,12 @@ pub struct ClientConfig {
     pub server_addr: String,
     /// Path to PEM-encoded CA public certificate used to verify the server certificate
     pub tls_ca_cert_path: String,
+    /// Path to store the PEM-encoded client certificate issued by the server at bind
+    #[serde(default = "default_tls_client_cert_path")]
+    pub tls_client_cert_path: String,
+    /// Path to store the PEM-encoded private key of the client certificate
+    #[serde(default = "default_tls_client_key_path")]
+    pub tls_client_key_path: String,
     /// Path to PEM-encoded reverse proxy certificate used to enable HTTP2 on client side
     pub tls_reverse_cert_path: String,
     /// Path to PEM-encoded reverse proxy key
@@ -65,6 +177,10 @@ pub struct ClientConfig {
     pub reverse_addr: String,
     /// Path for client Caddyfile, the default one is /etc/caddy/Caddyfile
     pub caddyfile: String,
+    /// Optional Caddyfile template used instead of the built-in one, see assets/Caddyfile.template
+    /// for the available `{{variable}}` placeholders
+    #[serde(default)]
+    pub caddyfile_template: Option<String>,
     /// Adress for DomJudge server, this will be inserted into Caddyfile as reverse proxy upstream,
     /// make sure it does not end with a slash
     pub domjudge_addr: String,
@@ -74,4 +190,81 @@ pub struct ClientConfig {
     pub player_user: String,
     /// System user password for player
     pub player_user_password: String,
+    /// Load synced config through the Caddy admin API instead of reloading the service,
+    /// rewriting the Caddyfile and reloading is kept as fallback when the API fails
+    #[serde(default = "default_caddy_admin_api")]
+    pub caddy_admin_api: bool,
+    /// Listen address of the Caddy admin API, also written as `admin` option into the Caddyfile
+    #[serde(default = "default_caddy_admin_addr")]
+    pub caddy_admin_addr: String,
+    /// Seconds between monitor heartbeats, keep it in line with the server `heartbeat_interval_secs`
+    #[serde(default = "default_report_interval_secs")]
+    pub report_interval_secs: u64,
+    /// Up to this many random seconds are added to every heartbeat delay,
+    /// so clients coming back from a network outage do not report in lockstep
+    #[serde(default = "default_report_jitter_secs")]
+    pub report_jitter_secs: u64,
+    /// Failed heartbeats are retried after an interval doubling up to this many seconds
+    #[serde(default = "default_report_max_backoff_secs")]
+    pub report_max_backoff_secs: u64,
+    /// File keeping sync reports that could not be delivered, sent once the server is reachable
+    #[serde(default = "default_outbox_path")]
+    pub outbox_path: String,
+    /// File keeping the hash of the last synced Caddyfile, monitor resyncs when the live one differs
+    #[serde(default = "default_caddyfile_hash_path")]
+    pub caddyfile_hash_path: String,
+    /// Root-only file recording the bound ID and the history of bind, sync, clean and session
+    /// operations, shown by `status`
+    #[serde(default = "default_state_path")]
+    pub state_path: String,
+}
+
+#[cfg(feature = "client")]
+fn default_tls_client_cert_path() -> String {
+    "/etc/natsume/client-cert.pem".to_string()
+}
+
+#[cfg(feature = "client")]
+fn default_tls_client_key_path() -> String {
+    "/etc/natsume/client-key.pem".to_string()
+}
+
+#[cfg(feature = "client")]
+fn default_caddy_admin_api() -> bool {
+    true
+}
+
+#[cfg(feature = "client")]
+fn default_caddy_admin_addr() -> String {
+    "localhost:20190".to_string()
+}
+
+#[cfg(feature = "client")]
+fn default_report_interval_secs() -> u64 {
+    60
+}
+
+#[cfg(feature = "client")]
+fn default_report_jitter_secs() -> u64 {
+    15
+}
+
+#[cfg(feature = "client")]
+fn default_report_max_backoff_secs() -> u64 {
+    10 * 60
+}
+
+#[cfg(feature = "client")]
+fn default_outbox_path() -> String {
+    "/var/lib/natsume/outbox.json".to_string()
+}
+
+#[cfg(feature = "client")]
+fn default_caddyfile_hash_path() -> String {
+    "/var/lib/natsume/caddyfile.sha256".to_string()
+}
+
+#[cfg(feature = "client")]
+fn default_state_path() -> String {
+    "/var/lib/natsume/state.json".to_string()
 }
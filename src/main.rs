@@ -48,8 +48,54 @@ enum Commands {
     /// Load ID info into database
     #[cfg(feature = "server")]
     Load {
-        #[arg(short, long, help = "CSV file containing id,username,password")]
+        #[arg(
+            short,
+            long,
+            help = "CSV file containing id,username,password, or DOMjudge accounts.yaml"
+        )]
         data_path: String,
+        #[arg(
+            short,
+            long,
+            value_enum,
+            default_value_t = server::RosterFormat::Csv,
+            help = "Format of the roster file"
+        )]
+        format: server::RosterFormat,
+        #[arg(
+            long,
+            help = "DOMjudge teams.json providing team name, organization and location"
+        )]
+        teams_path: Option<String>,
+        #[arg(
+            long,
+            help = "DOMjudge organizations.json resolving organization names"
+        )]
+        organizations_path: Option<String>,
+        #[arg(
+            long,
+            default_value = server::DEFAULT_CONTEST,
+            help = "Contest profile the roster belongs to"
+        )]
+        contest: String,
+        #[arg(long, help = "Print added, changed and removed IDs without writing")]
+        dry_run: bool,
+        #[arg(long, help = "Delete players missing in the new roster")]
+        prune: bool,
+    },
+
+    /// Load the seat map into database, replacing the current one
+    #[cfg(feature = "server")]
+    LoadSeats {
+        #[arg(short, long, help = "CSV file containing room,row,col,hostname,ip")]
+        seat_path: String,
+    },
+
+    /// Manage contest roster profiles
+    #[cfg(feature = "server")]
+    Contest {
+        #[command(subcommand)]
+        command: ContestCommands,
     },
 
     /// Bind the device to a ID
@@ -59,7 +105,7 @@ enum Commands {
             long,
             short,
             conflicts_with = "prompt",
-            required_unless_present = "prompt",
+            required_unless_present_any = ["prompt", "auto"],
             help = "ID for this device"
         )]
         id: Option<String>,
@@ -69,6 +115,12 @@ enum Commands {
             help = "Prompt for ID via yad GUI dialog"
         )]
         prompt: bool,
+        #[arg(
+            long,
+            conflicts_with_all = ["id", "prompt"],
+            help = "Let the server resolve the ID from hostname, IP or seat map, prompting via yad when it cannot"
+        )]
+        auto: bool,
         #[arg(long = "_bg", hide = true)]
         background: bool,
     },
@@ -85,20 +137,38 @@ enum Commands {
     #[cfg(feature = "client")]
     Monitor {},
 
+    /// Show the bound ID, last sync, server reachability and Caddy state of this device
+    #[cfg(feature = "client")]
+    Status {},
+
     /// Deal with user session
     #[cfg(feature = "client")]
     Session {
-        #[arg(value_enum, help = "Operation for session (terminate, autologin)")]
+        #[arg(
+            value_enum,
+            help = "Operation for session (terminate, autologin, lock)"
+        )]
         operation: SessionOperation,
     },
 }
 
+#[cfg(feature = "server")]
+#[derive(Subcommand)]
+enum ContestCommands {
+    /// Serve credentials from this contest profile and queue a sync for every bound seat
+    Activate { name: String },
+    /// List contest profiles and their player count
+    List {},
+}
+
 #[derive(clap::ValueEnum, Clone)]
 enum SessionOperation {
     /// Terminate the user session
     Terminate,
     /// Auto login to the given user session
     AutoLogin,
+    /// Lock the user session, e.g. at scoreboard freeze
+    Lock,
 }
 
 fn main() -> ExitCode {
@@ -149,7 +219,10 @@ fn main() -> ExitCode {
     {
         // Bind command should be run in non priviledged environment
         if !matches!(cli.command, Commands::Bind { .. }) {
-            if client::check_permission(config.client.caddyfile.clone()) {
+            if client::check_permission(
+                config.client.caddyfile.clone(),
+                !config.client.caddy_admin_api,
+            ) {
                 tracing::info!("Client priviledge correct, procedding.")
             } else {
                 tracing::error!("Client do not have root exec priviledge!!!");
@@ -157,7 +230,10 @@ fn main() -> ExitCode {
             }
         }
 
-        if client::check_prerequisite() {
+        // Status should still work when Caddy is down, it reports that itself
+        if matches!(cli.command, Commands::Status {}) {
+            tracing::info!("Skipping prerequisite check for status");
+        } else if client::check_prerequisite() {
             tracing::info!("Client prerequisite matched, procedding.")
         } else {
             tracing::error!("Client prerequisite does not match!!!");
@@ -215,9 +291,25 @@ fn main() -> ExitCode {
             }
         }
         #[cfg(feature = "server")]
-        Commands::Load { data_path } => {
+        Commands::Load {
+            data_path,
+            contest,
+            format,
+            teams_path,
+            organizations_path,
+            dry_run,
+            prune,
+        } => {
             tracing::info!("Staring data load");
-            match server::load_data(data_path) {
+            match server::load_data(
+                data_path,
+                contest,
+                format,
+                teams_path,
+                organizations_path,
+                dry_run,
+                prune,
+            ) {
                 Ok(_) => {
                     tracing::info!("Data successfully loaded into database!");
                     ExitCode::SUCCESS
@@ -228,15 +320,45 @@ fn main() -> ExitCode {
                 }
             }
         }
+        #[cfg(feature = "server")]
+        Commands::LoadSeats { seat_path } => {
+            tracing::info!("Staring seat map load");
+            match server::load_seats(seat_path) {
+                Ok(_) => {
+                    tracing::info!("Seat map successfully loaded into database!");
+                    ExitCode::SUCCESS
+                }
+                Err(err) => {
+                    tracing::error!("Seat map load failed with error {:#}", err);
+                    ExitCode::FAILURE
+                }
+            }
+        }
+        #[cfg(feature = "server")]
+        Commands::Contest { command } => {
+            let result = match command {
+                ContestCommands::Activate { name } => server::activate_contest(name),
+                ContestCommands::List {} => server::list_contests(),
+            };
+            match result {
+                Ok(_) => ExitCode::SUCCESS,
+                Err(err) => {
+                    tracing::error!("Contest command failed with error {:#}", err);
+                    ExitCode::FAILURE
+                }
+            }
+        }
         #[cfg(feature = "client")]
         Commands::Bind {
             id,
             prompt,
+            auto,
             background,
         } => {
             let options = client::BindOptions {
                 id,
                 prompt,
+                auto,
                 background,
             };
             match client::bind_ip(options, &config_path) {
@@ -284,6 +406,14 @@ fn main() -> ExitCode {
             }
         },
         #[cfg(feature = "client")]
+        Commands::Status {} => match client::print_status() {
+            Ok(_) => ExitCode::SUCCESS,
+            Err(err) => {
+                tracing::error!("Status failed with error {:#}", err);
+                ExitCode::FAILURE
+            }
+        },
+        #[cfg(feature = "client")]
         Commands::Session { operation } => match operation {
             SessionOperation::Terminate => match client::terminate_sessions() {
                 Ok(_) => {
@@ -305,6 +435,16 @@ fn main() -> ExitCode {
                     ExitCode::FAILURE
                 }
             },
+            SessionOperation::Lock => match client::lock_sessions() {
+                Ok(_) => {
+                    tracing::info!("Lock user session successful");
+                    ExitCode::SUCCESS
+                }
+                Err(err) => {
+                    tracing::error!("Lock user session failed with error {:#}", err);
+                    ExitCode::FAILURE
+                }
+            },
         },
     }
 }
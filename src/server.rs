@@ -1,16 +1,17 @@
-use std::{fs, io::BufReader, path::Path};
+use std::{collections::HashSet, fs, io::BufReader, net::IpAddr, path::Path};
 
 use actix_cors::Cors;
 use actix_web::{
     App, HttpResponse, HttpServer,
     body::{BoxBody, MessageBody, to_bytes},
     dev::ServiceResponse,
+    guard,
     http::header,
     middleware::{ErrorHandlerResponse, ErrorHandlers},
     web,
 };
 use diesel::{
-    dsl::{exists, insert_into, select, update},
+    dsl::{delete, insert_into, insert_or_ignore_into, update},
     prelude::*,
 };
 use rcgen::{CertificateParams, Issuer, KeyPair};
@@ -19,11 +20,23 @@ use rustls_pemfile::{certs, pkcs8_private_keys};
 use serde::Deserialize;
 use serde_json::json;
 use services::spa_handler;
+use toggle::Toggle;
 use tracing_unwrap::OptionExt;
 
+mod auto_bind;
+mod contest;
+mod credential;
 mod database;
+mod pki;
+mod presence;
+mod roster;
 mod schema;
 mod services;
+mod timeline;
+mod toggle;
+
+pub use contest::{DEFAULT_CONTEST, activate_contest, list_contests};
+pub use roster::RosterFormat;
 
 fn ensure_parent_dir(path: &str) -> std::io::Result<()> {
     if let Some(parent) = Path::new(path).parent()
@@ -42,6 +55,18 @@ where
     B: MessageBody + 'static,
     <B as MessageBody>::Error: actix_web::ResponseError,
 {
+    // Structured errors already carry `msg` and `error`, keep their extra fields
+    let is_json = res
+        .response()
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|value| value.as_bytes().starts_with(b"application/json"));
+    if is_json {
+        return Ok(ErrorHandlerResponse::Response(
+            res.map_into_boxed_body().map_into_right_body(),
+        ));
+    }
+
     let (req, res) = res.into_parts();
     let status = res.status();
     let error_msg = status
@@ -76,6 +101,27 @@ pub async fn serve() -> std::io::Result<()> {
 
     database::init_database().map_err(std::io::Error::other)?;
 
+    let auto_bind_rules = auto_bind::parse_auto_bind_rules(&server_config.server.auto_bind)
+        .map_err(std::io::Error::other)?;
+    auto_bind::AUTO_BIND_RULES
+        .set(auto_bind_rules)
+        .map_err(|_| std::io::Error::other("AUTO_BIND_RULES already inited!"))?;
+
+    let timeline_steps =
+        timeline::parse_timeline(&server_config.server.timeline).map_err(std::io::Error::other)?;
+    actix_web::rt::spawn(timeline::run_timeline(
+        timeline_steps,
+        server_config.server.timeline_grace_secs,
+    ));
+    actix_web::rt::spawn(presence::run_presence_monitor(
+        server_config.server.heartbeat_interval_secs,
+        server_config.server.offline_missed_heartbeats,
+        server_config.server.heartbeat_retention_secs,
+        server_config.server.seat_event_retention_secs,
+        server_config.server.flap_window_secs,
+        server_config.server.flap_offline_count,
+    ));
+
     let ca_cert_pem =
         fs::read_to_string(&server_config.server.tls_ca_cert_path).map_err(|err| {
             std::io::Error::other(format!(
@@ -91,8 +137,9 @@ pub async fn serve() -> std::io::Result<()> {
     })?;
 
     let ca_key = KeyPair::from_pem(&ca_key_pem).map_err(std::io::Error::other)?;
-    let ca_issuer =
-        Issuer::from_ca_cert_pem(&ca_cert_pem, ca_key).map_err(std::io::Error::other)?;
+    let ca_issuer = pki::CA_ISSUER.get_or_try_init(|| {
+        Issuer::from_ca_cert_pem(&ca_cert_pem, ca_key).map_err(std::io::Error::other)
+    })?;
 
     let tls_cert_path = &server_config.server.tls_cert_path;
     let tls_key_path = &server_config.server.tls_key_path;
@@ -123,7 +170,7 @@ pub async fn serve() -> std::io::Result<()> {
 
             let signing_key = KeyPair::generate().map_err(std::io::Error::other)?;
             let cert = certificate_params
-                .signed_by(&signing_key, &ca_issuer)
+                .signed_by(&signing_key, ca_issuer)
                 .map_err(std::io::Error::other)?;
 
             let cert_chain_pem = format!("{}\n{ca_cert_pem}", cert.pem());
@@ -165,7 +212,7 @@ pub async fn serve() -> std::io::Result<()> {
     })?;
 
     let tls_config = rustls::ServerConfig::builder()
-        .with_no_client_auth()
+        .with_client_cert_verifier(pki::build_client_verifier(&ca_cert_pem)?)
         .with_single_cert(cert_chain, PrivateKeyDer::Pkcs8(key))
         .map_err(std::io::Error::other)?;
 
@@ -174,79 +221,218 @@ pub async fn serve() -> std::io::Result<()> {
     }
 
     HttpServer::new(|| {
-        let mut app = App::new()
+        App::new()
             .wrap(ErrorHandlers::new().default_handler(add_error_header))
             .wrap(Cors::permissive())
             .service(services::get_ip)
             .service(services::bind_id)
+            .service(services::auto_bind_id)
+            .service(services::poll_bind_decision)
+            .service(services::list_pending_binds)
+            .service(services::approve_pending_bind)
+            .service(services::reject_pending_bind)
             .service(services::report_status)
             .service(services::get_status)
+            .service(services::get_status_stream)
+            .service(services::get_history)
             .service(services::sync_info)
             .service(services::remove_bind)
-            .service(web::scope("/panel").default_service(web::to(spa_handler)));
-        let static_file_enabled = crate::GLOBAL_CONFIG
-            .get()
-            .unwrap()
-            .server
-            .enable_static_file;
-        if static_file_enabled {
-            app = app.service(actix_files::Files::new("/static", "./static"));
-        }
-        app
+            .service(services::set_player_password)
+            .service(services::reveal_player_password)
+            .service(services::queue_command)
+            .service(services::list_commands)
+            .service(services::list_audit_events)
+            .service(services::export_audit_events)
+            .service(services::get_toggles)
+            .service(services::set_toggles)
+            .service(web::scope("/panel").default_service(web::to(spa_handler)))
+            // Always mounted, the guard follows the runtime toggle so no restart is needed
+            .service(
+                actix_files::Files::new("/static", "./static")
+                    .guard(guard::fn_guard(|_| Toggle::StaticFile.enabled())),
+            )
     })
+    .on_connect(pki::extract_client_certificate)
     .bind_rustls_0_23(("0.0.0.0", server_config.server.port), tls_config)?
     .run()
     .await
 }
 
+/// Load the roster of one contest profile in one transaction, rows missing in the new roster are
+/// only deleted with `prune`
+pub fn load_data(
+    data_path: String,
+    contest_name: String,
+    format: RosterFormat,
+    teams_path: Option<String>,
+    organizations_path: Option<String>,
+    dry_run: bool,
+    prune: bool,
+) -> anyhow::Result<()> {
+    let infos = roster::read_roster(
+        format,
+        &data_path,
+        teams_path.as_deref(),
+        organizations_path.as_deref(),
+    )?;
+    tracing::info!(
+        "Read {} player(s) of contest {} from {}",
+        infos.len(),
+        contest_name,
+        data_path
+    );
+    database::init_database()?;
+
+    let connection_pool = database::DB_CONNECTION_POOL.get().unwrap_or_log();
+    let mut connection = connection_pool.get()?;
+
+    use schema::contest::dsl as contest_dsl;
+    use schema::player::dsl::*;
+    let mut current = player
+        .filter(contest.eq(&contest_name))
+        .select((id, username, password, team_name, organization, location))
+        .load::<roster::PlayerInfo>(&mut connection)?;
+    for info in current.iter_mut() {
+        info.password = credential::decrypt_password(&info.password)?;
+    }
+    let diff = roster::diff_roster(&current, &infos);
+    diff.log(prune);
+    if dry_run {
+        tracing::info!("Dry run, database left untouched");
+        return Ok(());
+    }
+
+    let added = diff.added.iter().collect::<HashSet<&String>>();
+    let changed = diff.changed.iter().collect::<HashSet<&String>>();
+    connection.transaction::<_, anyhow::Error, _>(|connection| {
+        // New profiles stay inactive until `contest activate`
+        insert_or_ignore_into(contest_dsl::contest)
+            .values(contest_dsl::name.eq(&contest_name))
+            .execute(connection)?;
+        for info in &infos {
+            if added.contains(&info.id) {
+                insert_into(player)
+                    .values((
+                        contest.eq(&contest_name),
+                        id.eq(&info.id),
+                        username.eq(&info.username),
+                        password.eq(credential::encrypt_password(&info.password)?),
+                        synced.eq(false as i32),
+                        team_name.eq(&info.team_name),
+                        organization.eq(&info.organization),
+                        location.eq(&info.location),
+                    ))
+                    .execute(connection)?;
+            } else if changed.contains(&info.id) {
+                update(player.find((&contest_name, &info.id)))
+                    .set((
+                        username.eq(&info.username),
+                        password.eq(credential::encrypt_password(&info.password)?),
+                        synced.eq(false as i32),
+                        team_name.eq(&info.team_name),
+                        organization.eq(&info.organization),
+                        location.eq(&info.location),
+                    ))
+                    .execute(connection)?;
+            }
+        }
+        if prune {
+            delete(
+                player
+                    .filter(contest.eq(&contest_name))
+                    .filter(id.eq_any(&diff.removed)),
+            )
+            .execute(connection)?;
+        }
+        services::record_audit(
+            connection,
+            services::AuditEvent::RosterLoaded,
+            &services::AuditActor::cli(),
+            Some(&contest_name),
+            &format!(
+                "{} added, {} changed, {} {} from {}",
+                diff.added.len(),
+                diff.changed.len(),
+                diff.removed.len(),
+                if prune { "removed" } else { "kept" },
+                data_path
+            ),
+        );
+        Ok(())
+    })?;
+    Ok(())
+}
+
 #[derive(Deserialize, Debug)]
-struct PlayerInfo {
-    id: String,
-    username: String,
-    password: String,
+struct SeatInfo {
+    room: String,
+    row: i32,
+    col: i32,
+    hostname: String,
+    ip: String,
+    /// Contest ID of the player at this seat, used by auto bind
+    #[serde(default)]
+    id: Option<String>,
 }
 
-pub fn load_data(data_path: String) -> anyhow::Result<()> {
-    let mut rdr = csv::Reader::from_path(data_path)?;
-    let mut infos = Vec::<PlayerInfo>::new();
+/// Replace the seat map, the file is checked for duplicate IPs, positions and IDs before writing
+pub fn load_seats(seat_path: String) -> anyhow::Result<()> {
+    let mut rdr = csv::Reader::from_path(seat_path)?;
+    let mut seats = Vec::<SeatInfo>::new();
+    let mut ips = HashSet::new();
+    let mut positions = HashSet::new();
+    let mut ids = HashSet::new();
     for row in rdr.deserialize() {
-        let row: PlayerInfo = row?;
-        infos.push(row);
+        let seat: SeatInfo = row?;
+        if seat.ip.parse::<IpAddr>().is_err() {
+            anyhow::bail!(
+                "Invalid IP {} for seat {} row {} col {}",
+                seat.ip,
+                seat.room,
+                seat.row,
+                seat.col
+            );
+        }
+        if !ips.insert(seat.ip.clone()) {
+            anyhow::bail!("Duplicate IP {} in seat map", seat.ip);
+        }
+        if !positions.insert((seat.room.clone(), seat.row, seat.col)) {
+            anyhow::bail!(
+                "Duplicate seat {} row {} col {} in seat map",
+                seat.room,
+                seat.row,
+                seat.col
+            );
+        }
+        if let Some(id) = &seat.id
+            && !ids.insert(id.clone())
+        {
+            anyhow::bail!("Duplicate ID {} in seat map", id);
+        }
+        seats.push(seat);
     }
     database::init_database()?;
 
     let connection_pool = database::DB_CONNECTION_POOL.get().unwrap_or_log();
+    let mut connection = connection_pool.get()?;
 
-    use schema::player::dsl::*;
-    for info in infos {
-        // Check if id key is present
-        let mut connection = connection_pool.get()?;
-        let exist =
-            select(exists(player.filter(id.eq(&info.id)))).get_result::<bool>(&mut connection)?;
-
-        if exist {
-            // Data exist, this should happen between the warmup contest and official contest
-            update(player.filter(id.eq(&info.id)))
-                .set((
-                    username.eq(&info.username),
-                    password.eq(&info.password),
-                    synced.eq(false as i32),
-                ))
-                .execute(&mut connection)?;
-            tracing::info!("ID {} data exist, updated", &info.id);
-        } else {
-            // Data does not exist, inserting new one.
-            // This should happen when first setup
-            tracing::info!("ID {} data don't exist, inserting", &info.id);
-            insert_into(player)
+    use schema::seat::dsl as seat_dsl;
+    connection.transaction::<_, anyhow::Error, _>(|connection| {
+        delete(seat_dsl::seat).execute(connection)?;
+        for seat in &seats {
+            insert_into(seat_dsl::seat)
                 .values((
-                    id.eq(&info.id),
-                    username.eq(&info.username),
-                    password.eq(&info.password),
-                    synced.eq(false as i32),
+                    seat_dsl::ip.eq(&seat.ip),
+                    seat_dsl::room.eq(&seat.room),
+                    seat_dsl::row.eq(seat.row),
+                    seat_dsl::col.eq(seat.col),
+                    seat_dsl::hostname.eq(&seat.hostname),
+                    seat_dsl::bind_id.eq(&seat.id),
                 ))
-                .execute(&mut connection)?;
+                .execute(connection)?;
         }
-    }
+        Ok(())
+    })?;
+    tracing::info!("Seat map replaced with {} seat(s)", seats.len());
     Ok(())
 }
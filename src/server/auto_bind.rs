@@ -0,0 +1,77 @@
+use std::net::IpAddr;
+
+use anyhow::bail;
+use ipnet::IpNet;
+use once_cell::sync::OnceCell;
+use regex_lite::Regex;
+
+use crate::config::AutoBindRule;
+
+pub static AUTO_BIND_RULES: OnceCell<Vec<AutoBindMatcher>> = OnceCell::new();
+
+pub struct AutoBindMatcher {
+    hostname: Option<Regex>,
+    cidr: Option<IpNet>,
+    id: String,
+}
+
+/// Validate the configured auto bind rules so a bad regex or CIDR fails at startup
+pub fn parse_auto_bind_rules(rules: &[AutoBindRule]) -> anyhow::Result<Vec<AutoBindMatcher>> {
+    let mut matchers = Vec::new();
+    for rule in rules {
+        if rule.hostname.is_none() && rule.cidr.is_none() {
+            bail!("Auto bind rule for ID {} needs a hostname or cidr", rule.id);
+        }
+        let hostname = match &rule.hostname {
+            Some(pattern) => match Regex::new(pattern) {
+                Ok(regex) => Some(regex),
+                Err(err) => bail!(
+                    "Invalid hostname regex {} in auto bind rule: {err}",
+                    pattern
+                ),
+            },
+            None => None,
+        };
+        let cidr = match &rule.cidr {
+            Some(cidr) => match cidr.parse::<IpNet>() {
+                Ok(net) => Some(net),
+                Err(err) => bail!("Invalid cidr {} in auto bind rule: {err}", cidr),
+            },
+            None => None,
+        };
+        matchers.push(AutoBindMatcher {
+            hostname,
+            cidr,
+            id: rule.id.clone(),
+        });
+    }
+    Ok(matchers)
+}
+
+impl AutoBindMatcher {
+    /// Every condition of the rule has to match, a hostname rule never matches without hostname
+    fn resolve(&self, hostname: Option<&str>, ip: IpAddr) -> Option<String> {
+        if let Some(cidr) = &self.cidr
+            && !cidr.contains(&ip)
+        {
+            return None;
+        }
+        match &self.hostname {
+            Some(regex) => {
+                let captures = regex.captures(hostname?)?;
+                let mut id = String::new();
+                captures.expand(&self.id, &mut id);
+                Some(id)
+            }
+            None => Some(self.id.clone()),
+        }
+    }
+}
+
+/// ID of the first rule matching this machine
+pub fn resolve_auto_bind(hostname: Option<&str>, ip: IpAddr) -> Option<String> {
+    AUTO_BIND_RULES
+        .get()?
+        .iter()
+        .find_map(|matcher| matcher.resolve(hostname, ip))
+}
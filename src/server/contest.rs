@@ -0,0 +1,103 @@
+use chrono::Utc;
+use diesel::{
+    dsl::{exists, select, update},
+    prelude::*,
+};
+use tracing_unwrap::OptionExt;
+
+use super::schema::contest::dsl as contest_dsl;
+use super::schema::id_bind::dsl as id_bind_dsl;
+use super::schema::player::dsl as player_dsl;
+use super::services::{AuditActor, AuditEvent, RemoteCommand, enqueue_commands, record_audit};
+
+pub const DEFAULT_CONTEST: &str = "default";
+
+/// Subquery selecting the name of the active contest profile, every player lookup filters
+/// `player.contest` through it so switching contests is a single update of the contest table
+#[diesel::dsl::auto_type]
+pub fn active_contest() -> _ {
+    let active: bool = true;
+    contest_dsl::contest
+        .filter(contest_dsl::active.eq(active))
+        .select(contest_dsl::name)
+}
+
+/// Make the named profile the one served by sync, and queue a sync for every seat bound to one
+/// of its players so machines pick up its credentials. Activating the previous profile again reverts the switch.
+pub fn activate_contest(name: String) -> anyhow::Result<()> {
+    super::database::init_database()?;
+    let connection_pool = super::database::DB_CONNECTION_POOL.get().unwrap_or_log();
+    let mut connection = connection_pool.get()?;
+
+    connection.transaction::<_, anyhow::Error, _>(|connection| {
+        let known =
+            select(exists(contest_dsl::contest.find(&name))).get_result::<bool>(connection)?;
+        if !known {
+            anyhow::bail!(
+                "Unknown contest {}, load a roster with --contest first",
+                name
+            );
+        }
+
+        update(contest_dsl::contest)
+            .set(contest_dsl::active.eq(contest_dsl::name.eq(&name)))
+            .execute(connection)?;
+        update(contest_dsl::contest.find(&name))
+            .set(contest_dsl::activated_at.eq(Utc::now().timestamp().to_string()))
+            .execute(connection)?;
+        // Seats still hold the credentials of the previous contest
+        update(player_dsl::player.filter(player_dsl::contest.eq(&name)))
+            .set(player_dsl::synced.eq(false as i32))
+            .execute(connection)?;
+
+        // Only seats bound to a player of this contest can sync, UNKNOWN rows never would
+        let macs = id_bind_dsl::id_bind
+            .filter(
+                id_bind_dsl::id.eq_any(
+                    player_dsl::player
+                        .filter(player_dsl::contest.eq(&name))
+                        .select(player_dsl::id),
+                ),
+            )
+            .select(id_bind_dsl::mac)
+            .load::<String>(connection)?;
+        enqueue_commands(connection, &macs, RemoteCommand::Sync, None)?;
+        tracing::info!(
+            "Contest {} activated, sync queued for {} bound seat(s)",
+            name,
+            macs.len()
+        );
+        record_audit(
+            connection,
+            AuditEvent::ContestActivated,
+            &AuditActor::cli(),
+            Some(&name),
+            &format!("Sync queued for {} bound seat(s)", macs.len()),
+        );
+        Ok(())
+    })
+}
+
+pub fn list_contests() -> anyhow::Result<()> {
+    super::database::init_database()?;
+    let connection_pool = super::database::DB_CONNECTION_POOL.get().unwrap_or_log();
+    let mut connection = connection_pool.get()?;
+
+    let contests = contest_dsl::contest
+        .order(contest_dsl::name.asc())
+        .select((contest_dsl::name, contest_dsl::active))
+        .load::<(String, bool)>(&mut connection)?;
+    for (name, active) in contests {
+        let player_count = player_dsl::player
+            .filter(player_dsl::contest.eq(&name))
+            .count()
+            .get_result::<i64>(&mut connection)?;
+        tracing::info!(
+            "{}{} with {} player(s)",
+            name,
+            if active { " (active)" } else { "" },
+            player_count
+        );
+    }
+    Ok(())
+}
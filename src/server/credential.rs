@@ -0,0 +1,153 @@
+use anyhow::bail;
+use base64::{Engine, engine::general_purpose::STANDARD};
+use diesel::{dsl::update, prelude::*};
+use once_cell::sync::OnceCell;
+use ring::aead::{AES_256_GCM, Aad, LessSafeKey, NONCE_LEN, Nonce, UnboundKey};
+
+use super::schema::player::dsl as player_dsl;
+
+/// Marks stored passwords as encrypted, rows from older versions are still plaintext
+const ENCRYPTED_PREFIX: &str = "enc:v1:";
+
+static PASSWORD_KEY: OnceCell<LessSafeKey> = OnceCell::new();
+
+/// Parse the base64 encoded 256-bit key from the server config
+pub fn init_password_key(encoded_key: Option<&str>) -> anyhow::Result<()> {
+    let Some(encoded_key) = encoded_key else {
+        bail!(
+            "password_key is missing from the [server] section of the config, generate one with \
+             `openssl rand -base64 32` and add it, player passwords are encrypted with it"
+        );
+    };
+    PASSWORD_KEY
+        .set(parse_password_key(encoded_key)?)
+        .map_err(|_| anyhow::Error::msg("PASSWORD_KEY already inited!"))?;
+    Ok(())
+}
+
+fn parse_password_key(encoded_key: &str) -> anyhow::Result<LessSafeKey> {
+    let key_bytes = STANDARD
+        .decode(encoded_key.trim())
+        .map_err(|err| anyhow::Error::msg(format!("Invalid password_key, err {err}")))?;
+    let Ok(unbound_key) = UnboundKey::new(&AES_256_GCM, &key_bytes) else {
+        bail!(
+            "password_key must be 32 bytes, got {} byte(s)",
+            key_bytes.len()
+        );
+    };
+    Ok(LessSafeKey::new(unbound_key))
+}
+
+fn password_key() -> anyhow::Result<&'static LessSafeKey> {
+    match PASSWORD_KEY.get() {
+        Some(key) => Ok(key),
+        None => bail!("Password key not initialized"),
+    }
+}
+
+pub fn encrypt_password(password: &str) -> anyhow::Result<String> {
+    encrypt_with(password_key()?, password)
+}
+
+pub fn decrypt_password(stored: &str) -> anyhow::Result<String> {
+    decrypt_with(password_key()?, stored)
+}
+
+fn encrypt_with(key: &LessSafeKey, password: &str) -> anyhow::Result<String> {
+    let nonce_bytes = rand::random::<[u8; NONCE_LEN]>();
+    let mut sealed = password.as_bytes().to_vec();
+    key.seal_in_place_append_tag(
+        Nonce::assume_unique_for_key(nonce_bytes),
+        Aad::empty(),
+        &mut sealed,
+    )
+    .map_err(|_| anyhow::Error::msg("Failed to encrypt password"))?;
+
+    let mut payload = nonce_bytes.to_vec();
+    payload.extend_from_slice(&sealed);
+    Ok(format!("{ENCRYPTED_PREFIX}{}", STANDARD.encode(payload)))
+}
+
+fn decrypt_with(key: &LessSafeKey, stored: &str) -> anyhow::Result<String> {
+    let Some(encoded) = stored.strip_prefix(ENCRYPTED_PREFIX) else {
+        bail!("Password is not encrypted");
+    };
+    let mut payload = STANDARD.decode(encoded)?;
+    if payload.len() < NONCE_LEN {
+        bail!("Encrypted password too short");
+    }
+    let mut sealed = payload.split_off(NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(&payload)
+        .map_err(|_| anyhow::Error::msg("Invalid password nonce"))?;
+    // Fails as well when the key in the config changed
+    let plaintext = key
+        .open_in_place(nonce, Aad::empty(), &mut sealed)
+        .map_err(|_| anyhow::Error::msg("Failed to decrypt password, check password_key"))?;
+    Ok(String::from_utf8(plaintext.to_vec())?)
+}
+
+/// Encrypt passwords still stored in plaintext by an older version
+pub fn encrypt_legacy_passwords(connection: &mut SqliteConnection) -> anyhow::Result<()> {
+    let plaintext_rows = player_dsl::player
+        .filter(player_dsl::password.not_like(format!("{ENCRYPTED_PREFIX}%")))
+        .select((player_dsl::contest, player_dsl::id, player_dsl::password))
+        .load::<(String, String, String)>(connection)?;
+    if plaintext_rows.is_empty() {
+        return Ok(());
+    }
+
+    connection.transaction::<_, anyhow::Error, _>(|connection| {
+        for (contest, id, password) in &plaintext_rows {
+            update(player_dsl::player.find((contest, id)))
+                .set(player_dsl::password.eq(encrypt_password(password)?))
+                .execute(connection)?;
+        }
+        Ok(())
+    })?;
+    tracing::info!(
+        "Encrypted {} plaintext password(s) at rest",
+        plaintext_rows.len()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(byte: u8) -> LessSafeKey {
+        parse_password_key(&STANDARD.encode([byte; 32])).unwrap()
+    }
+
+    #[test]
+    fn password_round_trips() {
+        let key = key(1);
+        let stored = encrypt_with(&key, "p@ss word").unwrap();
+        assert!(stored.starts_with(ENCRYPTED_PREFIX));
+        assert!(!stored.contains("p@ss word"));
+        assert_eq!(decrypt_with(&key, &stored).unwrap(), "p@ss word");
+        // Fresh nonce per password
+        assert_ne!(encrypt_with(&key, "p@ss word").unwrap(), stored);
+    }
+
+    #[test]
+    fn decrypt_fails_with_wrong_key() {
+        let stored = encrypt_with(&key(1), "secret").unwrap();
+        let err = decrypt_with(&key(2), &stored).unwrap_err();
+        assert!(err.to_string().contains("check password_key"));
+    }
+
+    #[test]
+    fn decrypt_rejects_plaintext_and_truncated_payloads() {
+        let key = key(1);
+        assert!(decrypt_with(&key, "secret").is_err());
+        assert!(decrypt_with(&key, &format!("{ENCRYPTED_PREFIX}AAAA")).is_err());
+    }
+
+    #[test]
+    fn password_key_must_be_32_bytes() {
+        assert!(parse_password_key(&STANDARD.encode([0u8; 16])).is_err());
+        assert!(parse_password_key("not base64!").is_err());
+        assert!(init_password_key(None).is_err());
+    }
+}
@@ -42,6 +42,14 @@ fn run_migrations(
     Ok(())
 }
 
+/// Fresh in-memory database with every migration applied
+#[cfg(test)]
+pub(crate) fn test_connection() -> SqliteConnection {
+    let mut connection = SqliteConnection::establish(":memory:").unwrap();
+    run_migrations(&mut connection).unwrap();
+    connection
+}
+
 pub fn get_connection_pool() -> Pool<ConnectionManager<SqliteConnection>> {
     let manager = ConnectionManager::<SqliteConnection>::new("database.db");
     Pool::builder()
@@ -57,6 +65,14 @@ pub fn init_database() -> anyhow::Result<()> {
     let mut connection = connection_pool.get()?;
     run_migrations(&mut connection)?;
 
+    let server_config = &crate::GLOBAL_CONFIG
+        .get()
+        .ok_or(anyhow::Error::msg("Global config not initialized"))?
+        .server;
+    super::credential::init_password_key(server_config.password_key.as_deref())?;
+    super::credential::encrypt_legacy_passwords(&mut connection)?;
+    super::toggle::init_toggles(&mut connection, server_config)?;
+
     DB_CONNECTION_POOL
         .set(connection_pool)
         .map_err(|_| anyhow::Error::msg("DB_CONNECTION_POOL already inited!"))?;
@@ -0,0 +1,94 @@
+use std::{any::Any, io::BufReader, sync::Arc};
+
+use actix_tls::accept::rustls_0_23::TlsStream;
+use actix_web::{dev::Extensions, rt::net::TcpStream};
+use once_cell::sync::OnceCell;
+use rcgen::{CertificateParams, DnType, Issuer, KeyPair};
+use rustls::{
+    RootCertStore,
+    server::{WebPkiClientVerifier, danger::ClientCertVerifier},
+};
+use rustls_pemfile::certs;
+use sha2::{Digest, Sha256};
+
+pub static CA_ISSUER: OnceCell<Issuer<'static, KeyPair>> = OnceCell::new();
+
+/// Fingerprint of the certificate a client presented during the TLS handshake
+#[derive(Clone)]
+pub struct ClientCertificate {
+    pub fingerprint: String,
+}
+
+pub struct IssuedCertificate {
+    pub cert_pem: String,
+    pub key_pem: String,
+    pub fingerprint: String,
+}
+
+pub fn certificate_fingerprint(der: &[u8]) -> String {
+    hex::encode(Sha256::digest(der))
+}
+
+/// Issue a client auth certificate for one machine, the MAC is kept as common name for inspection
+/// only, requests are matched to a machine by the fingerprint stored at bind time.
+pub fn issue_client_certificate(mac: &str) -> anyhow::Result<IssuedCertificate> {
+    let Some(issuer) = CA_ISSUER.get() else {
+        anyhow::bail!("CA issuer not initialized");
+    };
+
+    let mut certificate_params = CertificateParams::new(Vec::<String>::new())?;
+    certificate_params
+        .distinguished_name
+        .push(DnType::CommonName, mac);
+    certificate_params.is_ca = rcgen::IsCa::NoCa;
+    certificate_params.use_authority_key_identifier_extension = true;
+    certificate_params
+        .extended_key_usages
+        .push(rcgen::ExtendedKeyUsagePurpose::ClientAuth);
+
+    let signing_key = KeyPair::generate()?;
+    let cert = certificate_params.signed_by(&signing_key, issuer)?;
+
+    Ok(IssuedCertificate {
+        cert_pem: cert.pem(),
+        key_pem: signing_key.serialize_pem(),
+        fingerprint: certificate_fingerprint(cert.der()),
+    })
+}
+
+/// Throwaway CA so tests can issue client certificates
+#[cfg(test)]
+pub(crate) fn init_test_issuer() {
+    CA_ISSUER.get_or_init(|| {
+        let mut ca_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        ca_params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+        Issuer::new(ca_params, KeyPair::generate().unwrap())
+    });
+}
+
+/// Ask every client for a certificate signed by our CA, but let the handshake succeed without one
+/// so bind and panel keep working, handlers decide whether a certificate is required.
+pub fn build_client_verifier(ca_cert_pem: &str) -> std::io::Result<Arc<dyn ClientCertVerifier>> {
+    let mut root_store = RootCertStore::empty();
+    for cert in certs(&mut BufReader::new(ca_cert_pem.as_bytes())) {
+        root_store.add(cert?).map_err(std::io::Error::other)?;
+    }
+
+    WebPkiClientVerifier::builder(Arc::new(root_store))
+        .allow_unauthenticated()
+        .build()
+        .map_err(std::io::Error::other)
+}
+
+/// `on_connect` hook storing the verified client certificate in the connection data
+pub fn extract_client_certificate(connection: &dyn Any, data: &mut Extensions) {
+    let Some(tls_stream) = connection.downcast_ref::<TlsStream<TcpStream>>() else {
+        return;
+    };
+    let (_, session) = tls_stream.get_ref();
+    if let Some(cert) = session.peer_certificates().and_then(|certs| certs.first()) {
+        data.insert(ClientCertificate {
+            fingerprint: certificate_fingerprint(cert.as_ref()),
+        });
+    }
+}
@@ -0,0 +1,213 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use diesel::{
+    dsl::{count_star, delete, insert_into, update},
+    prelude::*,
+};
+use tracing_unwrap::OptionExt;
+
+use super::schema::heartbeat::dsl as heartbeat_dsl;
+use super::schema::id_bind::dsl as id_bind_dsl;
+use super::schema::seat_event::dsl as seat_event_dsl;
+use super::services::{StatusEventKind, publish_status};
+
+pub const EVENT_ONLINE: &str = "online";
+pub const EVENT_OFFLINE: &str = "offline";
+/// Recorded when a seat reaches the offline count of the flap window
+pub const EVENT_FLAPPING: &str = "flapping";
+
+/// Seats going offline `offline_count` times within `window_secs` are flapping
+struct FlapDetection {
+    window_secs: i64,
+    offline_count: i64,
+}
+
+fn insert_event(
+    connection: &mut SqliteConnection,
+    mac: &str,
+    event: &str,
+    now: i64,
+) -> QueryResult<usize> {
+    insert_into(seat_event_dsl::seat_event)
+        .values((
+            seat_event_dsl::mac.eq(mac),
+            seat_event_dsl::event.eq(event),
+            seat_event_dsl::created_at.eq(now),
+        ))
+        .execute(connection)
+}
+
+/// Append to the heartbeat history and bring the seat back online if it was marked offline
+pub fn record_heartbeat(connection: &mut SqliteConnection, mac: &str, ip: &str) -> QueryResult<()> {
+    let now = Utc::now().timestamp();
+    connection.transaction(|connection| {
+        insert_into(heartbeat_dsl::heartbeat)
+            .values((
+                heartbeat_dsl::mac.eq(mac),
+                heartbeat_dsl::ip.eq(ip),
+                heartbeat_dsl::received_at.eq(now),
+            ))
+            .execute(connection)?;
+
+        let came_back = update(
+            id_bind_dsl::id_bind
+                .filter(id_bind_dsl::mac.eq(mac))
+                .filter(id_bind_dsl::online.eq(false)),
+        )
+        .set(id_bind_dsl::online.eq(true))
+        .execute(connection)?;
+        if came_back > 0 {
+            tracing::info!("MAC {} is back online", mac);
+            insert_event(connection, mac, EVENT_ONLINE, now)?;
+        }
+        Ok(())
+    })
+}
+
+/// Offline transitions of every seat within the window, seats without any are left out
+pub fn count_offline_events(
+    connection: &mut SqliteConnection,
+    window_secs: i64,
+    mac: Option<&str>,
+) -> QueryResult<Vec<(String, i64)>> {
+    let since = Utc::now().timestamp() - window_secs;
+    let mut statement = seat_event_dsl::seat_event
+        .filter(seat_event_dsl::event.eq(EVENT_OFFLINE))
+        .filter(seat_event_dsl::created_at.ge(since))
+        .group_by(seat_event_dsl::mac)
+        .select((seat_event_dsl::mac, count_star()))
+        .into_boxed();
+    if let Some(mac) = mac {
+        statement = statement.filter(seat_event_dsl::mac.eq(mac));
+    }
+    statement.load(connection)
+}
+
+/// Flag the seat once its offline count within the window reaches the limit
+fn check_flapping(
+    connection: &mut SqliteConnection,
+    mac: &str,
+    now: i64,
+    flap: &FlapDetection,
+) -> QueryResult<()> {
+    let offline_count = count_offline_events(connection, flap.window_secs, Some(mac))?
+        .first()
+        .map(|(_, count)| *count)
+        .unwrap_or_default();
+    if offline_count == flap.offline_count {
+        tracing::warn!(
+            "MAC {} went offline {} times within {}s, flagging as flapping",
+            mac,
+            offline_count,
+            flap.window_secs
+        );
+        insert_event(connection, mac, EVENT_FLAPPING, now)?;
+    }
+    Ok(())
+}
+
+fn mark_offline(
+    connection: &mut SqliteConnection,
+    threshold_secs: i64,
+    flap: &FlapDetection,
+) -> QueryResult<()> {
+    let now = Utc::now().timestamp();
+    let seats = id_bind_dsl::id_bind
+        .filter(id_bind_dsl::online.eq(true))
+        .select((id_bind_dsl::mac, id_bind_dsl::last_seen))
+        .load::<(String, String)>(connection)?;
+
+    for (mac, last_seen) in seats {
+        let Ok(last_seen_secs) = last_seen.parse::<i64>() else {
+            tracing::warn!("MAC {} has invalid last seen {}", mac, last_seen);
+            continue;
+        };
+        if now - last_seen_secs <= threshold_secs {
+            continue;
+        }
+
+        let went_offline = connection.transaction(|connection| {
+            // Matching last_seen skips seats that reported since they were loaded
+            let affected = update(
+                id_bind_dsl::id_bind
+                    .filter(id_bind_dsl::mac.eq(&mac))
+                    .filter(id_bind_dsl::last_seen.eq(&last_seen)),
+            )
+            .set(id_bind_dsl::online.eq(false))
+            .execute(connection)?;
+            if affected > 0 {
+                tracing::warn!(
+                    "MAC {} marked offline, last seen {}s ago",
+                    mac,
+                    now - last_seen_secs
+                );
+                insert_event(connection, &mac, EVENT_OFFLINE, now)?;
+                check_flapping(connection, &mac, now, flap)?;
+            }
+            QueryResult::Ok(affected > 0)
+        })?;
+        if went_offline {
+            publish_status(connection, StatusEventKind::Presence, &mac);
+        }
+    }
+    Ok(())
+}
+
+fn prune_heartbeats(connection: &mut SqliteConnection, retention_secs: i64) -> QueryResult<usize> {
+    let cutoff = Utc::now().timestamp() - retention_secs;
+    delete(heartbeat_dsl::heartbeat.filter(heartbeat_dsl::received_at.lt(cutoff)))
+        .execute(connection)
+}
+
+fn prune_seat_events(connection: &mut SqliteConnection, retention_secs: i64) -> QueryResult<usize> {
+    let cutoff = Utc::now().timestamp() - retention_secs;
+    delete(seat_event_dsl::seat_event.filter(seat_event_dsl::created_at.lt(cutoff)))
+        .execute(connection)
+}
+
+pub async fn run_presence_monitor(
+    heartbeat_interval_secs: i64,
+    offline_missed_heartbeats: i64,
+    heartbeat_retention_secs: i64,
+    seat_event_retention_secs: i64,
+    flap_window_secs: i64,
+    flap_offline_count: i64,
+) {
+    let threshold_secs = heartbeat_interval_secs * offline_missed_heartbeats;
+    let flap = FlapDetection {
+        window_secs: flap_window_secs,
+        offline_count: flap_offline_count,
+    };
+    let mut interval =
+        actix_web::rt::time::interval(Duration::from_secs(heartbeat_interval_secs.max(1) as u64));
+    loop {
+        interval.tick().await;
+        let connection_pool = super::database::DB_CONNECTION_POOL.get().unwrap_or_log();
+        let mut connection = match connection_pool.get() {
+            Ok(conn) => conn,
+            Err(err) => {
+                tracing::error!("Error getting database connection {}", err);
+                continue;
+            }
+        };
+
+        if let Err(err) = mark_offline(&mut connection, threshold_secs, &flap) {
+            tracing::error!("Error marking offline seats, err {}", err);
+        }
+        match prune_heartbeats(&mut connection, heartbeat_retention_secs) {
+            Ok(0) => {}
+            Ok(count) => tracing::debug!("Pruned {} heartbeat record(s)", count),
+            Err(err) => tracing::error!("Error pruning heartbeat history, err {}", err),
+        }
+        // Never prune inside the flap window, the offline count relies on it
+        match prune_seat_events(
+            &mut connection,
+            seat_event_retention_secs.max(flap.window_secs),
+        ) {
+            Ok(0) => {}
+            Ok(count) => tracing::debug!("Pruned {} seat event(s)", count),
+            Err(err) => tracing::error!("Error pruning seat events, err {}", err),
+        }
+    }
+}
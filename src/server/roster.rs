@@ -0,0 +1,244 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::Path,
+};
+
+use anyhow::bail;
+use diesel::Queryable;
+use serde::Deserialize;
+
+/// Roster file layouts accepted by `load`
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub enum RosterFormat {
+    /// CSV with `id,username,password` and optional `team_name,organization,location`
+    Csv,
+    /// DOMjudge `accounts.yaml`, optionally joined with `teams.json` and `organizations.json`
+    Domjudge,
+}
+
+#[derive(Deserialize, Queryable, PartialEq, Debug)]
+pub struct PlayerInfo {
+    pub id: String,
+    pub username: String,
+    pub password: String,
+    #[serde(default)]
+    pub team_name: Option<String>,
+    #[serde(default)]
+    pub organization: Option<String>,
+    /// Seat or location description shown in panel
+    #[serde(default)]
+    pub location: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct DomjudgeAccount {
+    #[serde(default)]
+    id: Option<String>,
+    username: String,
+    password: String,
+    #[serde(rename = "type")]
+    account_type: String,
+    #[serde(default)]
+    team_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct DomjudgeTeam {
+    id: String,
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    display_name: Option<String>,
+    #[serde(default)]
+    organization_id: Option<String>,
+    #[serde(default)]
+    location: Option<DomjudgeLocation>,
+}
+
+#[derive(Deserialize)]
+struct DomjudgeLocation {
+    #[serde(default)]
+    description: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct DomjudgeOrganization {
+    id: String,
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    formal_name: Option<String>,
+}
+
+fn read_csv_roster(data_path: &Path) -> anyhow::Result<Vec<PlayerInfo>> {
+    let mut rdr = csv::Reader::from_path(data_path)?;
+    let mut infos = Vec::<PlayerInfo>::new();
+    for row in rdr.deserialize() {
+        let row: PlayerInfo = row?;
+        infos.push(row);
+    }
+    Ok(infos)
+}
+
+/// Team accounts become players keyed by their team ID, the same ID printed on the seat
+fn read_domjudge_roster(
+    accounts_path: &Path,
+    teams_path: Option<&Path>,
+    organizations_path: Option<&Path>,
+) -> anyhow::Result<Vec<PlayerInfo>> {
+    let accounts: Vec<DomjudgeAccount> =
+        serde_yaml_ng::from_str(&fs::read_to_string(accounts_path)?)?;
+
+    let teams = match teams_path {
+        Some(path) => serde_json::from_str::<Vec<DomjudgeTeam>>(&fs::read_to_string(path)?)?
+            .into_iter()
+            .map(|team| (team.id.clone(), team))
+            .collect::<HashMap<String, DomjudgeTeam>>(),
+        None => HashMap::new(),
+    };
+    let organizations = match organizations_path {
+        Some(path) => {
+            serde_json::from_str::<Vec<DomjudgeOrganization>>(&fs::read_to_string(path)?)?
+                .into_iter()
+                .filter_map(|organization| {
+                    let name = organization.formal_name.or(organization.name)?;
+                    Some((organization.id, name))
+                })
+                .collect::<HashMap<String, String>>()
+        }
+        None => HashMap::new(),
+    };
+
+    let mut infos = Vec::new();
+    for account in accounts {
+        // Judges and admins have no seat to bind
+        if account.account_type != "team" {
+            continue;
+        }
+        let id = match account.team_id.or(account.id) {
+            Some(id) => id,
+            None => account.username.clone(),
+        };
+        let team = teams.get(&id);
+        if teams_path.is_some() && team.is_none() {
+            bail!(
+                "Team {} of account {} missing in teams.json",
+                id,
+                account.username
+            );
+        }
+        infos.push(PlayerInfo {
+            team_name: team.and_then(|team| team.display_name.clone().or(team.name.clone())),
+            // Fall back to the organization ID when organizations.json is not given
+            organization: team.and_then(|team| team.organization_id.as_ref()).map(
+                |organization_id| {
+                    organizations
+                        .get(organization_id)
+                        .cloned()
+                        .unwrap_or_else(|| organization_id.clone())
+                },
+            ),
+            location: team
+                .and_then(|team| team.location.as_ref())
+                .and_then(|location| location.description.clone()),
+            id,
+            username: account.username,
+            password: account.password,
+        });
+    }
+    Ok(infos)
+}
+
+pub fn read_roster(
+    format: RosterFormat,
+    data_path: &str,
+    teams_path: Option<&str>,
+    organizations_path: Option<&str>,
+) -> anyhow::Result<Vec<PlayerInfo>> {
+    let infos = match format {
+        RosterFormat::Csv => {
+            if teams_path.is_some() || organizations_path.is_some() {
+                bail!("teams.json and organizations.json are only read with the domjudge format");
+            }
+            read_csv_roster(Path::new(data_path))?
+        }
+        RosterFormat::Domjudge => read_domjudge_roster(
+            Path::new(data_path),
+            teams_path.map(Path::new),
+            organizations_path.map(Path::new),
+        )?,
+    };
+
+    let mut ids = HashSet::new();
+    for info in &infos {
+        if !ids.insert(info.id.as_str()) {
+            bail!("Duplicate ID {} in roster", info.id);
+        }
+    }
+    Ok(infos)
+}
+
+/// IDs touched by loading a roster, in roster order for added and changed
+pub struct RosterDiff {
+    pub added: Vec<String>,
+    pub changed: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+impl RosterDiff {
+    pub fn log(&self, prune: bool) {
+        tracing::info!(
+            "{} added, {} changed, {} removed",
+            self.added.len(),
+            self.changed.len(),
+            self.removed.len()
+        );
+        if !self.added.is_empty() {
+            tracing::info!("Added: {}", self.added.join(", "));
+        }
+        if !self.changed.is_empty() {
+            tracing::info!("Changed: {}", self.changed.join(", "));
+        }
+        if !self.removed.is_empty() {
+            if prune {
+                tracing::info!("Removed: {}", self.removed.join(", "));
+            } else {
+                tracing::warn!(
+                    "Missing in roster, kept without --prune: {}",
+                    self.removed.join(", ")
+                );
+            }
+        }
+    }
+}
+
+pub fn diff_roster(current: &[PlayerInfo], incoming: &[PlayerInfo]) -> RosterDiff {
+    let current_by_id = current
+        .iter()
+        .map(|info| (info.id.as_str(), info))
+        .collect::<HashMap<&str, &PlayerInfo>>();
+    let incoming_ids = incoming
+        .iter()
+        .map(|info| info.id.as_str())
+        .collect::<HashSet<&str>>();
+
+    let mut diff = RosterDiff {
+        added: Vec::new(),
+        changed: Vec::new(),
+        removed: Vec::new(),
+    };
+    for info in incoming {
+        match current_by_id.get(info.id.as_str()) {
+            None => diff.added.push(info.id.clone()),
+            Some(existing) if *existing != info => diff.changed.push(info.id.clone()),
+            Some(_) => {}
+        }
+    }
+    diff.removed = current
+        .iter()
+        .filter(|info| !incoming_ids.contains(info.id.as_str()))
+        .map(|info| info.id.clone())
+        .collect();
+    diff
+}
@@ -1,5 +1,48 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    audit_event (id) {
+        id -> Integer,
+        event -> Text,
+        actor -> Text,
+        mac -> Nullable<Text>,
+        ip -> Nullable<Text>,
+        target -> Nullable<Text>,
+        detail -> Text,
+        created_at -> BigInt,
+    }
+}
+
+diesel::table! {
+    command_queue (id) {
+        id -> Integer,
+        mac -> Text,
+        command -> Text,
+        status -> Text,
+        result -> Nullable<Text>,
+        created_at -> Text,
+        updated_at -> Text,
+        timeline_step -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    contest (name) {
+        name -> Text,
+        active -> Bool,
+        activated_at -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    heartbeat (id) {
+        id -> Integer,
+        mac -> Text,
+        ip -> Text,
+        received_at -> BigInt,
+    }
+}
+
 diesel::table! {
     id_bind (mac) {
         mac -> Text,
@@ -7,16 +50,110 @@ diesel::table! {
         ip -> Text,
         client_version -> Text,
         last_seen -> Text,
+        sync_error -> Nullable<Text>,
+        online -> Bool,
+        cert_fingerprint -> Nullable<Text>,
+        machine_fingerprint -> Nullable<Text>,
+        reported_fingerprint -> Nullable<Text>,
+        caddy_drift -> Nullable<Text>,
+        caddy_drift_at -> Nullable<Text>,
     }
 }
 
 diesel::table! {
-    player (id) {
+    pending_bind (id) {
+        id -> Integer,
+        mac -> Text,
+        bind_id -> Text,
+        ip -> Text,
+        client_version -> Text,
+        reason -> Text,
+        secret -> Text,
+        status -> Text,
+        cert_pem -> Nullable<Text>,
+        key_pem -> Nullable<Text>,
+        created_at -> Text,
+        decided_at -> Nullable<Text>,
+        machine_fingerprint -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    player (contest, id) {
+        contest -> Text,
         id -> Text,
         username -> Text,
         password -> Text,
         synced -> Integer,
+        team_name -> Nullable<Text>,
+        organization -> Nullable<Text>,
+        location -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    runtime_toggle (name) {
+        name -> Text,
+        enabled -> Bool,
+        updated_at -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    seat (ip) {
+        ip -> Text,
+        room -> Text,
+        row -> Integer,
+        col -> Integer,
+        hostname -> Text,
+        bind_id -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    seat_event (id) {
+        id -> Integer,
+        mac -> Text,
+        event -> Text,
+        created_at -> BigInt,
+    }
+}
+
+diesel::table! {
+    telemetry (mac) {
+        mac -> Text,
+        caddy_active -> Bool,
+        graphical_session -> Bool,
+        uptime_secs -> Nullable<BigInt>,
+        load_avg -> Nullable<Double>,
+        home_free_bytes -> Nullable<BigInt>,
+        clock_offset_ms -> BigInt,
+        hostname -> Nullable<Text>,
+        updated_at -> Text,
+    }
+}
+
+diesel::table! {
+    timeline_step (name) {
+        name -> Text,
+        command -> Text,
+        status -> Text,
+        target_count -> Integer,
+        executed_at -> Text,
     }
 }
 
-diesel::allow_tables_to_appear_in_same_query!(id_bind, player,);
+diesel::allow_tables_to_appear_in_same_query!(
+    audit_event,
+    command_queue,
+    contest,
+    heartbeat,
+    id_bind,
+    pending_bind,
+    player,
+    runtime_toggle,
+    seat,
+    seat_event,
+    telemetry,
+    timeline_step,
+);
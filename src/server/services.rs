@@ -1,21 +1,53 @@
+mod audit;
 mod bind;
+mod command;
+mod history;
 mod ip;
 mod panel;
+mod pending_bind;
+mod player;
 mod report;
 mod status;
+mod stream;
 mod sync;
+mod toggle;
 use std::future::Ready;
 
-use actix_web::{FromRequest, HttpRequest, dev::Payload, error::ErrorUnauthorized};
+use actix_web::{
+    FromRequest, HttpRequest,
+    dev::Payload,
+    error::{ErrorForbidden, ErrorInternalServerError, ErrorUnauthorized},
+};
+pub use audit::{AuditActor, AuditEvent, export_audit_events, list_audit_events, record_audit};
+pub use bind::auto_bind_id;
 pub use bind::bind_id;
 pub use bind::remove_bind;
+pub use command::{RemoteCommand, enqueue_commands, list_commands, queue_command};
+use diesel::{ExpressionMethods, OptionalExtension, QueryDsl, RunQueryDsl};
+pub use history::get_history;
 pub use ip::get_ip;
 pub use panel::spa_handler;
+pub use pending_bind::{
+    approve_pending_bind, list_pending_binds, poll_bind_decision, reject_pending_bind,
+};
+pub use player::{reveal_player_password, set_player_password};
 pub use report::report_status;
 pub use status::get_status;
+pub use stream::{StatusEventKind, get_status_stream, publish_status};
 pub use sync::sync_info;
+pub use toggle::{get_toggles, set_toggles};
+use tracing_unwrap::OptionExt;
 
-pub struct Authenticated;
+/// Panel request carrying the valid panel token
+pub struct Authenticated {
+    pub ip: Option<String>,
+}
+
+impl Authenticated {
+    pub fn actor(&self) -> AuditActor {
+        AuditActor::panel(self.ip.clone())
+    }
+}
 
 impl FromRequest for Authenticated {
     type Error = actix_web::Error;
@@ -29,7 +61,9 @@ impl FromRequest for Authenticated {
                 if let Ok(token) = header_value.to_str()
                     && token == config.server.panel_token
                 {
-                    return std::future::ready(Ok(Authenticated));
+                    return std::future::ready(Ok(Authenticated {
+                        ip: req.peer_addr().map(|addr| addr.ip().to_string()),
+                    }));
                 }
                 std::future::ready(Err(ErrorUnauthorized("Invalid token")))
             }
@@ -37,3 +71,88 @@ impl FromRequest for Authenticated {
         }
     }
 }
+
+/// Machine identified by the client certificate presented in the TLS handshake
+pub struct ClientIdentity {
+    /// MAC bound to the certificate, `None` only when `require_client_cert` is disabled
+    /// and the client presented no certificate
+    pub mac: Option<String>,
+}
+
+impl ClientIdentity {
+    /// MAC to act on, a self-declared MAC that differs from the certificate is ignored
+    pub fn resolve(&self, declared_mac: &str) -> String {
+        match &self.mac {
+            Some(mac) => {
+                if mac != declared_mac {
+                    tracing::warn!(
+                        "Certificate of MAC {} declared MAC {}, using the certificate one",
+                        mac,
+                        declared_mac
+                    );
+                }
+                mac.clone()
+            }
+            None => declared_mac.to_string(),
+        }
+    }
+}
+
+impl FromRequest for ClientIdentity {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let config = crate::GLOBAL_CONFIG.get().expect("Config not initialized");
+
+        let Some(certificate) = req.conn_data::<crate::server::pki::ClientCertificate>() else {
+            if config.server.require_client_cert {
+                return std::future::ready(Err(ErrorUnauthorized(
+                    "Missing client certificate, bind this machine first",
+                )));
+            }
+            return std::future::ready(Ok(ClientIdentity { mac: None }));
+        };
+
+        let connection_pool = crate::server::database::DB_CONNECTION_POOL
+            .get()
+            .unwrap_or_log();
+        let mut connection = match connection_pool.get() {
+            Ok(conn) => conn,
+            Err(err) => {
+                tracing::error!("Error getting database connection {}", err);
+                return std::future::ready(Err(ErrorInternalServerError("Database unavailable")));
+            }
+        };
+
+        use crate::server::schema::id_bind::dsl as id_bind_dsl;
+        match id_bind_dsl::id_bind
+            .filter(id_bind_dsl::cert_fingerprint.eq(&certificate.fingerprint))
+            .select(id_bind_dsl::mac)
+            .first::<String>(&mut connection)
+            .optional()
+        {
+            Ok(Some(mac)) => std::future::ready(Ok(ClientIdentity { mac: Some(mac) })),
+            Ok(None) => {
+                tracing::warn!(
+                    "Client certificate {} is not bound to any machine",
+                    certificate.fingerprint
+                );
+                record_audit(
+                    &mut connection,
+                    AuditEvent::CertificateRejected,
+                    &AuditActor::client(None, req.peer_addr().map(|addr| addr.ip().to_string())),
+                    None,
+                    &format!("Unbound certificate {}", certificate.fingerprint),
+                );
+                std::future::ready(Err(ErrorForbidden(
+                    "Client certificate revoked, bind this machine again",
+                )))
+            }
+            Err(err) => {
+                tracing::error!("Error looking up client certificate, err {}", err);
+                std::future::ready(Err(ErrorInternalServerError("Database unavailable")))
+            }
+        }
+    }
+}
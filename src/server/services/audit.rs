@@ -0,0 +1,289 @@
+use actix_web::{HttpResponse, Responder, get, http::header, web::Query};
+use chrono::Utc;
+use diesel::{dsl::insert_into, prelude::*};
+use serde::{Deserialize, Serialize};
+use tracing_unwrap::OptionExt;
+
+use crate::server::schema::audit_event;
+use crate::server::schema::audit_event::dsl as audit_dsl;
+
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 500;
+
+/// Security relevant actions kept for post-contest incident review
+#[derive(Clone, Copy, Debug)]
+pub enum AuditEvent {
+    Bind,
+    BindRejected,
+    BindQueued,
+    BindDisabled,
+    Unbind,
+    Sync,
+    SyncDisabled,
+    SyncDenied,
+    UnknownMacReport,
+    CertificateRejected,
+    CommandQueued,
+    PendingBindApproved,
+    PendingBindRejected,
+    BindDecisionDelivered,
+    PasswordChanged,
+    PasswordRevealed,
+    ContestActivated,
+    RosterLoaded,
+    ToggleChanged,
+    MacChanged,
+    FingerprintMismatch,
+    CaddyDrift,
+}
+
+impl AuditEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditEvent::Bind => "bind",
+            AuditEvent::BindRejected => "bind_rejected",
+            AuditEvent::BindQueued => "bind_queued",
+            AuditEvent::BindDisabled => "bind_disabled",
+            AuditEvent::Unbind => "unbind",
+            AuditEvent::Sync => "sync",
+            AuditEvent::SyncDisabled => "sync_disabled",
+            AuditEvent::SyncDenied => "sync_denied",
+            AuditEvent::UnknownMacReport => "unknown_mac_report",
+            AuditEvent::CertificateRejected => "certificate_rejected",
+            AuditEvent::CommandQueued => "command_queued",
+            AuditEvent::PendingBindApproved => "pending_bind_approved",
+            AuditEvent::PendingBindRejected => "pending_bind_rejected",
+            AuditEvent::BindDecisionDelivered => "bind_decision_delivered",
+            AuditEvent::PasswordChanged => "password_changed",
+            AuditEvent::PasswordRevealed => "password_revealed",
+            AuditEvent::ContestActivated => "contest_activated",
+            AuditEvent::RosterLoaded => "roster_loaded",
+            AuditEvent::ToggleChanged => "toggle_changed",
+            AuditEvent::MacChanged => "mac_changed",
+            AuditEvent::FingerprintMismatch => "fingerprint_mismatch",
+            AuditEvent::CaddyDrift => "caddy_drift",
+        }
+    }
+}
+
+/// Who triggered an audited action
+pub struct AuditActor {
+    /// `panel` for the panel token, `client` for a machine, `cli` for server subcommands
+    kind: &'static str,
+    mac: Option<String>,
+    ip: Option<String>,
+}
+
+impl AuditActor {
+    pub fn panel(ip: Option<String>) -> Self {
+        AuditActor {
+            kind: "panel",
+            mac: None,
+            ip,
+        }
+    }
+
+    pub fn client(mac: Option<String>, ip: Option<String>) -> Self {
+        AuditActor {
+            kind: "client",
+            mac,
+            ip,
+        }
+    }
+
+    pub fn cli() -> Self {
+        AuditActor {
+            kind: "cli",
+            mac: None,
+            ip: None,
+        }
+    }
+}
+
+/// Append one audit event. Failures are only logged, auditing never fails the audited request.
+pub fn record_audit(
+    connection: &mut SqliteConnection,
+    event: AuditEvent,
+    actor: &AuditActor,
+    target: Option<&str>,
+    detail: &str,
+) {
+    if let Err(err) = insert_into(audit_dsl::audit_event)
+        .values((
+            audit_dsl::event.eq(event.as_str()),
+            audit_dsl::actor.eq(actor.kind),
+            audit_dsl::mac.eq(&actor.mac),
+            audit_dsl::ip.eq(&actor.ip),
+            audit_dsl::target.eq(target),
+            audit_dsl::detail.eq(detail),
+            audit_dsl::created_at.eq(Utc::now().timestamp()),
+        ))
+        .execute(connection)
+    {
+        tracing::error!(
+            "Error recording audit event {}, err {}",
+            event.as_str(),
+            err
+        );
+    }
+}
+
+#[derive(Deserialize)]
+struct AuditQuery {
+    /// 1-based page number
+    page: Option<i64>,
+    per_page: Option<i64>,
+    event: Option<String>,
+    mac: Option<String>,
+    /// Only return events at or after this unix timestamp
+    since: Option<i64>,
+}
+
+#[derive(Serialize, Queryable)]
+struct AuditEventInfo {
+    id: i32,
+    event: String,
+    actor: String,
+    mac: Option<String>,
+    ip: Option<String>,
+    target: Option<String>,
+    detail: String,
+    created_at: i64,
+}
+
+#[derive(Serialize)]
+struct AuditResponse {
+    total: i64,
+    page: i64,
+    per_page: i64,
+    events: Vec<AuditEventInfo>,
+}
+
+fn filtered_events(query: &AuditQuery) -> audit_event::BoxedQuery<'_, diesel::sqlite::Sqlite> {
+    let mut statement = audit_dsl::audit_event.into_boxed();
+    if let Some(event) = &query.event {
+        statement = statement.filter(audit_dsl::event.eq(event));
+    }
+    if let Some(mac) = &query.mac {
+        statement = statement.filter(audit_dsl::mac.eq(mac));
+    }
+    if let Some(since) = query.since {
+        statement = statement.filter(audit_dsl::created_at.ge(since));
+    }
+    statement
+}
+
+#[get("/audit")]
+pub async fn list_audit_events(
+    _auth: crate::server::services::Authenticated,
+    query: Query<AuditQuery>,
+) -> impl Responder {
+    let connection_pool = crate::server::database::DB_CONNECTION_POOL
+        .get()
+        .unwrap_or_log();
+
+    let mut connection;
+    match connection_pool.get() {
+        Ok(conn) => {
+            connection = conn;
+        }
+        Err(err) => {
+            tracing::error!("Error getting database connection {}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+
+    let page = query.page.unwrap_or(1).max(1);
+    let per_page = query
+        .per_page
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+
+    let total = match filtered_events(&query)
+        .count()
+        .get_result::<i64>(&mut connection)
+    {
+        Ok(count) => count,
+        Err(err) => {
+            tracing::error!("Error counting audit events, err {}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    match filtered_events(&query)
+        .order(audit_dsl::id.desc())
+        .limit(per_page)
+        .offset((page - 1) * per_page)
+        .load::<AuditEventInfo>(&mut connection)
+    {
+        Ok(events) => HttpResponse::Ok().json(AuditResponse {
+            total,
+            page,
+            per_page,
+            events,
+        }),
+        Err(err) => {
+            tracing::error!("Error fetching audit events, err {}", err);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Whole filtered audit log as CSV, oldest first
+#[get("/audit/export")]
+pub async fn export_audit_events(
+    _auth: crate::server::services::Authenticated,
+    query: Query<AuditQuery>,
+) -> impl Responder {
+    let connection_pool = crate::server::database::DB_CONNECTION_POOL
+        .get()
+        .unwrap_or_log();
+
+    let mut connection;
+    match connection_pool.get() {
+        Ok(conn) => {
+            connection = conn;
+        }
+        Err(err) => {
+            tracing::error!("Error getting database connection {}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+
+    let events = match filtered_events(&query)
+        .order(audit_dsl::id.asc())
+        .load::<AuditEventInfo>(&mut connection)
+    {
+        Ok(events) => events,
+        Err(err) => {
+            tracing::error!("Error fetching audit events, err {}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for event in &events {
+        if let Err(err) = writer.serialize(event) {
+            tracing::error!("Error writing audit event {} as CSV, err {}", event.id, err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+    let body = match writer.into_inner() {
+        Ok(body) => body,
+        Err(err) => {
+            tracing::error!("Error flushing audit CSV, err {}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    HttpResponse::Ok()
+        .content_type("text/csv; charset=utf-8")
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            format!(
+                "attachment; filename=\"audit-{}.csv\"",
+                Utc::now().format("%Y%m%d-%H%M%S")
+            ),
+        ))
+        .body(body)
+}
@@ -1,10 +1,10 @@
-use actix_web::{HttpRequest, HttpResponse, Responder, post, web::Json};
+use actix_web::{HttpRequest, HttpResponse, Responder, http::StatusCode, post, web::Json};
 use chrono::Utc;
 use diesel::{
     dsl::{delete, exists, insert_into, select, update},
     prelude::*,
 };
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use tracing_unwrap::OptionExt;
 
 #[derive(Deserialize)]
@@ -13,7 +13,299 @@ struct BindRequestBody {
     id: String,
     #[serde(default)]
     client_version: Option<String>,
+    /// Checked against the seat map entry of the client IP
+    #[serde(default)]
+    hostname: Option<String>,
+    /// Machine fingerprint, recognizes the machine when its MAC changes
+    #[serde(default)]
+    fingerprint: Option<String>,
+}
+#[derive(Deserialize)]
+struct AutoBindRequestBody {
+    mac: String,
+    #[serde(default)]
+    client_version: Option<String>,
+    /// Matched against hostname rules and the seat map entry of the client IP
+    #[serde(default)]
+    hostname: Option<String>,
+    #[serde(default)]
+    fingerprint: Option<String>,
+}
+#[derive(Serialize)]
+struct BindResponseBody {
+    /// ID the MAC is now bound to, resolved by the server for auto bind
+    id: String,
+    /// PEM client certificate identifying this machine on sync and report
+    cert: String,
+    /// PEM private key of the client certificate
+    key: String,
+}
+
+/// Why a bind was refused, lets the client explain it to the person at the seat
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+enum BindRejectReason {
+    /// The ID is not in the player roster, most likely a typo
+    UnknownId,
+    /// The ID is already bound to another MAC
+    IdBound,
+    /// The ID is bound to another MAC reporting the same machine fingerprint, likely a swapped
+    /// network card. The fingerprint comes from the client, so staff still approve the move.
+    MacChanged,
+    /// The MAC is already bound to another ID and bind update is disabled
+    MacBound,
+    /// The MAC holds a client certificate the request did not present, so it can't prove to be
+    /// the bound machine
+    CertificateRequired,
+    /// A seat map is loaded and the client IP is not in it
+    UnknownSeat,
+    /// The hostname differs from the seat expected at the client IP, the machine was likely swapped
+    SeatMismatch,
+    /// Neither the seat map nor an auto bind rule gives an ID for this machine
+    Unresolved,
+}
+
+impl BindRejectReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BindRejectReason::UnknownId => "unknown_id",
+            BindRejectReason::IdBound => "id_bound",
+            BindRejectReason::MacChanged => "mac_changed",
+            BindRejectReason::MacBound => "mac_bound",
+            BindRejectReason::CertificateRequired => "certificate_required",
+            BindRejectReason::UnknownSeat => "unknown_seat",
+            BindRejectReason::SeatMismatch => "seat_mismatch",
+            BindRejectReason::Unresolved => "unresolved",
+        }
+    }
+}
+
+/// Error body with the usual `msg` and `error` fields plus the conflict details
+#[derive(Serialize)]
+struct BindRejectedResponse {
+    msg: String,
+    error: String,
+    reason: BindRejectReason,
+    id: String,
+    /// Hostname of the seat at the client IP
+    expected_hostname: Option<String>,
+}
+
+fn bind_rejected(
+    status: StatusCode,
+    reason: BindRejectReason,
+    error: String,
+    id: &str,
+    expected_hostname: Option<String>,
+) -> HttpResponse {
+    HttpResponse::build(status).json(BindRejectedResponse {
+        msg: status
+            .canonical_reason()
+            .unwrap_or("Unknown error")
+            .to_string(),
+        error,
+        reason,
+        id: id.to_string(),
+        expected_hostname,
+    })
+}
+
+/// Audit a bind attempt with the requesting machine as actor
+fn audit_bind(
+    connection: &mut SqliteConnection,
+    event: super::AuditEvent,
+    mac: &str,
+    client_ip: &str,
+    id: &str,
+    detail: &str,
+) {
+    super::record_audit(
+        connection,
+        event,
+        &super::AuditActor::client(Some(mac.to_string()), Some(client_ip.to_string())),
+        (!id.is_empty()).then_some(id),
+        detail,
+    );
+}
+
+#[derive(Serialize)]
+struct BindQueuedResponse {
+    reason: BindRejectReason,
+    id: String,
+    bound_mac: Option<String>,
+    bound_id: Option<String>,
+    /// Pending bind request the client polls `/bind/decision` with
+    request_id: i32,
+    secret: String,
+}
+
+/// Conflicting binds wait in the pending bind queue until the panel decides
+fn bind_queued(
+    connection: &mut SqliteConnection,
+    body: &BindRequestBody,
+    client_ip: &str,
+    reason: BindRejectReason,
+    bound_mac: Option<String>,
+    bound_id: Option<String>,
+) -> HttpResponse {
+    match super::pending_bind::queue_bind(
+        connection,
+        &body.mac,
+        &body.id,
+        client_ip,
+        body.client_version.as_deref().unwrap_or_default(),
+        reason.as_str(),
+        body.fingerprint.as_deref(),
+    ) {
+        Ok((request_id, secret)) => {
+            tracing::info!(
+                "Queued bind of MAC {} to ID {} as request {}",
+                body.mac,
+                body.id,
+                request_id
+            );
+            audit_bind(
+                connection,
+                super::AuditEvent::BindQueued,
+                &body.mac,
+                client_ip,
+                &body.id,
+                &format!("{} as pending request {}", reason.as_str(), request_id),
+            );
+            HttpResponse::Accepted().json(BindQueuedResponse {
+                reason,
+                id: body.id.clone(),
+                bound_mac,
+                bound_id,
+                request_id,
+                secret,
+            })
+        }
+        Err(err) => {
+            tracing::error!(
+                "Error queueing bind of MAC {} to ID {}, err {}",
+                body.mac,
+                body.id,
+                err
+            );
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Bind the MAC to the ID with a new certificate fingerprint, updating the row of a known MAC.
+/// A known MAC keeps the machine fingerprint recorded first, a different one is only stored as
+/// reported so the mismatch gets flagged. Without one the first fingerprint reported is adopted.
+pub(super) fn upsert_bind(
+    connection: &mut SqliteConnection,
+    mac: &str,
+    id: &str,
+    ip: &str,
+    client_version: &str,
+    cert_fingerprint: &str,
+    machine_fingerprint: Option<&str>,
+) -> QueryResult<()> {
+    use crate::server::schema::id_bind::dsl as id_bind_dsl;
+    let timestamp = Utc::now().timestamp().to_string();
+    let exist = select(exists(
+        id_bind_dsl::id_bind.filter(id_bind_dsl::mac.eq(mac)),
+    ))
+    .get_result::<bool>(connection)?;
+    if exist {
+        update(id_bind_dsl::id_bind.filter(id_bind_dsl::mac.eq(mac)))
+            .set((
+                id_bind_dsl::id.eq(id),
+                id_bind_dsl::ip.eq(ip),
+                id_bind_dsl::client_version.eq(client_version),
+                id_bind_dsl::last_seen.eq(&timestamp),
+                id_bind_dsl::cert_fingerprint.eq(cert_fingerprint),
+                // A new bind starts from a freshly synced machine
+                id_bind_dsl::caddy_drift.eq(None::<String>),
+                id_bind_dsl::caddy_drift_at.eq(None::<String>),
+            ))
+            .execute(connection)?;
+        check_fingerprint(
+            connection,
+            mac,
+            machine_fingerprint,
+            &super::AuditActor::client(Some(mac.to_string()), Some(ip.to_string())),
+        )?;
+    } else {
+        insert_into(id_bind_dsl::id_bind)
+            .values((
+                id_bind_dsl::mac.eq(mac),
+                id_bind_dsl::id.eq(id),
+                id_bind_dsl::ip.eq(ip),
+                id_bind_dsl::client_version.eq(client_version),
+                id_bind_dsl::last_seen.eq(&timestamp),
+                id_bind_dsl::cert_fingerprint.eq(cert_fingerprint),
+                id_bind_dsl::machine_fingerprint.eq(machine_fingerprint),
+                id_bind_dsl::reported_fingerprint.eq(machine_fingerprint),
+            ))
+            .execute(connection)?;
+    }
+    Ok(())
+}
+
+/// Store the machine fingerprint reported by a bound MAC and compare it with the one recorded at
+/// bind, returning whether they differ. A mismatch is audited once when it first shows up.
+pub(super) fn check_fingerprint(
+    connection: &mut SqliteConnection,
+    mac: &str,
+    reported: Option<&str>,
+    actor: &super::AuditActor,
+) -> QueryResult<bool> {
+    use crate::server::schema::id_bind::dsl as id_bind_dsl;
+    // Clients older than the fingerprint don't send one
+    let Some(reported) = reported else {
+        return Ok(false);
+    };
+    let Some((bound, previous)) = id_bind_dsl::id_bind
+        .filter(id_bind_dsl::mac.eq(mac))
+        .select((
+            id_bind_dsl::machine_fingerprint,
+            id_bind_dsl::reported_fingerprint,
+        ))
+        .first::<(Option<String>, Option<String>)>(connection)
+        .optional()?
+    else {
+        return Ok(false);
+    };
+
+    let Some(bound) = bound else {
+        update(id_bind_dsl::id_bind.filter(id_bind_dsl::mac.eq(mac)))
+            .set((
+                id_bind_dsl::machine_fingerprint.eq(reported),
+                id_bind_dsl::reported_fingerprint.eq(reported),
+            ))
+            .execute(connection)?;
+        return Ok(false);
+    };
+    if previous.as_deref() != Some(reported) {
+        update(id_bind_dsl::id_bind.filter(id_bind_dsl::mac.eq(mac)))
+            .set(id_bind_dsl::reported_fingerprint.eq(reported))
+            .execute(connection)?;
+    }
+
+    let mismatch = bound != reported;
+    if mismatch && previous.as_deref() != Some(reported) {
+        tracing::warn!(
+            "MAC {} reported machine fingerprint {} instead of {}, possible MAC spoofing or swapped machine!",
+            mac,
+            reported,
+            bound
+        );
+        super::record_audit(
+            connection,
+            super::AuditEvent::FingerprintMismatch,
+            actor,
+            Some(mac),
+            &format!("Bound {}, reported {}", bound, reported),
+        );
+    }
+    Ok(mismatch)
 }
+
 #[post("/bind")]
 pub async fn bind_id(req: HttpRequest, body: Json<BindRequestBody>) -> impl Responder {
     let client_ip;
@@ -24,11 +316,22 @@ pub async fn bind_id(req: HttpRequest, body: Json<BindRequestBody>) -> impl Resp
         return HttpResponse::InternalServerError().finish();
     }
 
-    let bind_enabled = crate::GLOBAL_CONFIG
+    let connection_pool = crate::server::database::DB_CONNECTION_POOL
         .get()
-        .expect_or_log("Global config not initialized!")
-        .server
-        .enable_bind;
+        .unwrap_or_log();
+
+    let mut connection;
+    match connection_pool.get() {
+        Ok(conn) => {
+            connection = conn;
+        }
+        Err(err) => {
+            tracing::error!("Error getting database connection {}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+
+    let bind_enabled = crate::server::toggle::Toggle::Bind.enabled();
 
     if !bind_enabled {
         tracing::warn!(
@@ -36,11 +339,36 @@ pub async fn bind_id(req: HttpRequest, body: Json<BindRequestBody>) -> impl Resp
             body.mac,
             body.id
         );
+        audit_bind(
+            &mut connection,
+            super::AuditEvent::BindDisabled,
+            &body.mac,
+            &client_ip,
+            &body.id,
+            "Bind service disabled",
+        );
         return HttpResponse::Forbidden()
             .body("Bind is not enabled! This request has been logged".to_string());
     }
 
-    // Write to database
+    bind_machine(
+        &mut connection,
+        &client_ip,
+        &body,
+        presented_certificate(&req).as_deref(),
+    )
+}
+
+#[post("/bind/auto")]
+pub async fn auto_bind_id(req: HttpRequest, body: Json<AutoBindRequestBody>) -> impl Responder {
+    let client_ip;
+    if let Some(value) = req.peer_addr() {
+        client_ip = value.ip();
+    } else {
+        tracing::error!("No IP can be extracted, this SHOULD NOT HAPPEN");
+        return HttpResponse::InternalServerError().finish();
+    }
+
     let connection_pool = crate::server::database::DB_CONNECTION_POOL
         .get()
         .unwrap_or_log();
@@ -56,11 +384,109 @@ pub async fn bind_id(req: HttpRequest, body: Json<BindRequestBody>) -> impl Resp
         }
     }
 
+    let bind_enabled = crate::server::toggle::Toggle::Bind.enabled();
+
+    if !bind_enabled {
+        tracing::warn!(
+            "MAC {} try to auto bind with bind service disabled!",
+            body.mac
+        );
+        audit_bind(
+            &mut connection,
+            super::AuditEvent::BindDisabled,
+            &body.mac,
+            &client_ip.to_string(),
+            "",
+            "Bind service disabled",
+        );
+        return HttpResponse::Forbidden()
+            .body("Bind is not enabled! This request has been logged".to_string());
+    }
+
+    // The seat map is the most specific source, rules only apply to seats without an ID
+    use crate::server::schema::seat::dsl as seat_dsl;
+    let seat_id = match seat_dsl::seat
+        .find(client_ip.to_string())
+        .select(seat_dsl::bind_id)
+        .first::<Option<String>>(&mut connection)
+        .optional()
+    {
+        Ok(result) => result.flatten(),
+        Err(err) => {
+            tracing::error!("Error fetching from database {}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+    let Some(id) = seat_id.or_else(|| {
+        crate::server::auto_bind::resolve_auto_bind(body.hostname.as_deref(), client_ip)
+    }) else {
+        tracing::warn!(
+            "No auto bind rule matched MAC {} with hostname {} from IP {}",
+            body.mac,
+            body.hostname.as_deref().unwrap_or("unknown"),
+            client_ip
+        );
+        audit_bind(
+            &mut connection,
+            super::AuditEvent::BindRejected,
+            &body.mac,
+            &client_ip.to_string(),
+            "",
+            BindRejectReason::Unresolved.as_str(),
+        );
+        return bind_rejected(
+            StatusCode::NOT_FOUND,
+            BindRejectReason::Unresolved,
+            format!("No ID could be resolved for IP {}", client_ip),
+            "",
+            None,
+        );
+    };
+    tracing::info!(
+        "Resolved ID {} for MAC {} from IP {}",
+        id,
+        body.mac,
+        client_ip
+    );
+
+    let body = body.into_inner();
+    let body = BindRequestBody {
+        mac: body.mac,
+        id,
+        client_version: body.client_version,
+        hostname: body.hostname,
+        fingerprint: body.fingerprint,
+    };
+    bind_machine(
+        &mut connection,
+        &client_ip.to_string(),
+        &body,
+        presented_certificate(&req).as_deref(),
+    )
+}
+
+/// Fingerprint of the client certificate presented in the TLS handshake, if any
+fn presented_certificate(req: &HttpRequest) -> Option<String> {
+    req.conn_data::<crate::server::pki::ClientCertificate>()
+        .map(|certificate| certificate.fingerprint.clone())
+}
+
+/// Shared by manual and auto bind once the ID is known. Rebinding a MAC that holds a client
+/// certificate requires presenting it, otherwise anyone with the bind token could take the seat.
+fn bind_machine(
+    connection: &mut SqliteConnection,
+    client_ip: &str,
+    body: &BindRequestBody,
+    presented_certificate: Option<&str>,
+) -> HttpResponse {
     use crate::server::schema::id_bind::dsl as id_bind_dsl;
-    let exist = match select(exists(
-        id_bind_dsl::id_bind.filter(id_bind_dsl::mac.eq(&body.mac)),
+    use crate::server::schema::player::dsl as player_dsl;
+    let player_exist = match select(exists(
+        player_dsl::player
+            .filter(player_dsl::contest.eq_any(crate::server::contest::active_contest()))
+            .filter(player_dsl::id.eq(&body.id)),
     ))
-    .get_result::<bool>(&mut connection)
+    .get_result::<bool>(connection)
     {
         Ok(result) => result,
         Err(err) => {
@@ -68,76 +494,238 @@ pub async fn bind_id(req: HttpRequest, body: Json<BindRequestBody>) -> impl Resp
             return HttpResponse::InternalServerError().finish();
         }
     };
+    if !player_exist {
+        tracing::warn!("MAC {} tried to bind to unknown ID {}", body.mac, body.id);
+        audit_bind(
+            connection,
+            super::AuditEvent::BindRejected,
+            &body.mac,
+            client_ip,
+            &body.id,
+            BindRejectReason::UnknownId.as_str(),
+        );
+        return bind_rejected(
+            StatusCode::NOT_FOUND,
+            BindRejectReason::UnknownId,
+            format!("ID {} is not in the player roster", body.id),
+            &body.id,
+            None,
+        );
+    }
 
-    let timestamp = Utc::now().timestamp().to_string();
-    if exist {
-        let bind_update_enabled = crate::GLOBAL_CONFIG
-            .get()
-            .expect_or_log("Global config not initialized!")
-            .server
-            .enable_bind_update;
-
-        if !bind_update_enabled {
-            tracing::warn!(
-                "MAC {} tried to bind to new ID {}, possible MAC collision!",
-                body.mac,
-                body.id
-            );
-            HttpResponse::Forbidden().finish()
-        } else {
-            // Update bind id
-            match update(id_bind_dsl::id_bind.filter(id_bind_dsl::mac.eq(&body.mac)))
-                .set((
-                    id_bind_dsl::id.eq(&body.id),
-                    id_bind_dsl::ip.eq(&client_ip),
-                    id_bind_dsl::client_version
-                        .eq(&body.client_version.as_deref().unwrap_or_default()),
-                    id_bind_dsl::last_seen.eq(&timestamp),
-                ))
-                .execute(&mut connection)
+    use crate::server::schema::seat::dsl as seat_dsl;
+    let seat_hostname = match seat_dsl::seat
+        .find(client_ip)
+        .select(seat_dsl::hostname)
+        .first::<String>(connection)
+        .optional()
+    {
+        Ok(result) => result,
+        Err(err) => {
+            tracing::error!("Error fetching from database {}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+    match seat_hostname {
+        Some(expected_hostname) => {
+            if let Some(hostname) = &body.hostname
+                && !hostname.eq_ignore_ascii_case(&expected_hostname)
             {
-                Ok(_) => {
-                    tracing::info!("Updated MAC {} ID {}", body.mac, body.id);
-                    HttpResponse::Ok().finish()
-                }
-                Err(err) => {
-                    tracing::error!(
-                        "Error updating MAC {} with ID {}, err {}",
-                        body.mac,
-                        body.id,
-                        err
-                    );
-                    HttpResponse::InternalServerError().finish()
-                }
+                tracing::warn!(
+                    "MAC {} with hostname {} tried to bind from IP {} of seat {}",
+                    body.mac,
+                    hostname,
+                    client_ip,
+                    expected_hostname
+                );
+                audit_bind(
+                    connection,
+                    super::AuditEvent::BindRejected,
+                    &body.mac,
+                    client_ip,
+                    &body.id,
+                    BindRejectReason::SeatMismatch.as_str(),
+                );
+                return bind_rejected(
+                    StatusCode::CONFLICT,
+                    BindRejectReason::SeatMismatch,
+                    format!(
+                        "Hostname {} does not match seat {} of IP {}",
+                        hostname, expected_hostname, client_ip
+                    ),
+                    &body.id,
+                    Some(expected_hostname),
+                );
             }
         }
-    } else {
-        // Insert new binding
-        match insert_into(id_bind_dsl::id_bind)
-            .values((
-                id_bind_dsl::mac.eq(&body.mac),
-                id_bind_dsl::id.eq(&body.id),
-                id_bind_dsl::ip.eq(&client_ip),
-                id_bind_dsl::client_version.eq(&body.client_version.as_deref().unwrap_or_default()),
-                id_bind_dsl::last_seen.eq(&timestamp),
-            ))
-            .execute(&mut connection)
-        {
-            Ok(_) => {
-                tracing::info!("Updated MAC {} ID {}", body.mac, body.id);
-                HttpResponse::Ok().finish()
-            }
-            Err(err) => {
-                tracing::error!(
-                    "Error inserting MAC {} with ID {}, err {}",
+        None => {
+            // Without a seat map every IP is accepted
+            let seat_map_loaded = match select(exists(seat_dsl::seat.select(seat_dsl::ip)))
+                .get_result::<bool>(connection)
+            {
+                Ok(result) => result,
+                Err(err) => {
+                    tracing::error!("Error fetching from database {}", err);
+                    return HttpResponse::InternalServerError().finish();
+                }
+            };
+            if seat_map_loaded {
+                tracing::warn!(
+                    "MAC {} tried to bind from IP {} missing in seat map",
                     body.mac,
-                    body.id,
-                    err
+                    client_ip
+                );
+                audit_bind(
+                    connection,
+                    super::AuditEvent::BindRejected,
+                    &body.mac,
+                    client_ip,
+                    &body.id,
+                    BindRejectReason::UnknownSeat.as_str(),
+                );
+                return bind_rejected(
+                    StatusCode::FORBIDDEN,
+                    BindRejectReason::UnknownSeat,
+                    format!("IP {} is not in the seat map", client_ip),
+                    &body.id,
+                    None,
                 );
-                HttpResponse::InternalServerError().finish()
             }
         }
     }
+
+    let id_bound = match id_bind_dsl::id_bind
+        .filter(id_bind_dsl::id.eq(&body.id))
+        .filter(id_bind_dsl::mac.ne(&body.mac))
+        .select((id_bind_dsl::mac, id_bind_dsl::machine_fingerprint))
+        .first::<(String, Option<String>)>(connection)
+        .optional()
+    {
+        Ok(result) => result,
+        Err(err) => {
+            tracing::error!("Error fetching from database {}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+    if let Some((bound_mac, bound_fingerprint)) = id_bound {
+        // Same machine coming back with another MAC hints at a swapped network card
+        let reason = if bound_fingerprint.is_some() && bound_fingerprint == body.fingerprint {
+            BindRejectReason::MacChanged
+        } else {
+            BindRejectReason::IdBound
+        };
+        tracing::warn!(
+            "MAC {} tried to bind to ID {} already bound to MAC {}, {}",
+            body.mac,
+            body.id,
+            bound_mac,
+            reason.as_str()
+        );
+        return bind_queued(connection, body, client_ip, reason, Some(bound_mac), None);
+    }
+
+    let bound = match id_bind_dsl::id_bind
+        .filter(id_bind_dsl::mac.eq(&body.mac))
+        .select((id_bind_dsl::id, id_bind_dsl::cert_fingerprint))
+        .first::<(String, Option<String>)>(connection)
+        .optional()
+    {
+        Ok(result) => result,
+        Err(err) => {
+            tracing::error!("Error fetching from database {}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+    if let Some((bound_id, Some(cert_fingerprint))) = &bound
+        && presented_certificate != Some(cert_fingerprint.as_str())
+    {
+        tracing::warn!(
+            "MAC {} tried to rebind to ID {} without its client certificate, possible MAC spoofing!",
+            body.mac,
+            body.id
+        );
+        return bind_queued(
+            connection,
+            body,
+            client_ip,
+            BindRejectReason::CertificateRequired,
+            None,
+            Some(bound_id.clone()),
+        );
+    }
+    let bound_id = bound.map(|(bound_id, _)| bound_id);
+
+    let bind_update_enabled = crate::server::toggle::Toggle::BindUpdate.enabled();
+    // MACs reporting before bind are logged as UNKNOWN and can be bound freely
+    if let Some(bound_id) = bound_id
+        && bound_id != "UNKNOWN"
+        && !bind_update_enabled
+    {
+        tracing::warn!(
+            "MAC {} tried to bind to new ID {}, possible MAC collision!",
+            body.mac,
+            body.id
+        );
+        return bind_queued(
+            connection,
+            body,
+            client_ip,
+            BindRejectReason::MacBound,
+            None,
+            Some(bound_id),
+        );
+    }
+
+    // Rebinding replaces the fingerprint, which revokes the previous certificate
+    let certificate = match crate::server::pki::issue_client_certificate(&body.mac) {
+        Ok(certificate) => certificate,
+        Err(err) => {
+            tracing::error!(
+                "Error issuing client certificate for MAC {}, err {:#}",
+                body.mac,
+                err
+            );
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+    let response = BindResponseBody {
+        id: body.id.clone(),
+        cert: certificate.cert_pem,
+        key: certificate.key_pem,
+    };
+
+    match upsert_bind(
+        connection,
+        &body.mac,
+        &body.id,
+        client_ip,
+        body.client_version.as_deref().unwrap_or_default(),
+        &certificate.fingerprint,
+        body.fingerprint.as_deref(),
+    ) {
+        Ok(_) => {
+            tracing::info!("Updated MAC {} ID {}", body.mac, body.id);
+            audit_bind(
+                connection,
+                super::AuditEvent::Bind,
+                &body.mac,
+                client_ip,
+                &body.id,
+                &format!("Certificate {}", certificate.fingerprint),
+            );
+            super::publish_status(connection, super::StatusEventKind::Bind, &body.mac);
+            HttpResponse::Ok().json(response)
+        }
+        Err(err) => {
+            tracing::error!(
+                "Error binding MAC {} with ID {}, err {}",
+                body.mac,
+                body.id,
+                err
+            );
+            HttpResponse::InternalServerError().finish()
+        }
+    }
 }
 
 #[derive(Deserialize)]
@@ -146,7 +734,7 @@ struct UnBindRequestBody {
 }
 #[post("/unbind")]
 pub async fn remove_bind(
-    _auth: crate::server::services::Authenticated,
+    auth: crate::server::services::Authenticated,
     body: Json<UnBindRequestBody>,
 ) -> impl Responder {
     let connection_pool = crate::server::database::DB_CONNECTION_POOL
@@ -186,6 +774,14 @@ pub async fn remove_bind(
         {
             Ok(_) => {
                 tracing::info!("Unbinded MAC {}", body.mac);
+                super::record_audit(
+                    &mut connection,
+                    super::AuditEvent::Unbind,
+                    &auth.actor(),
+                    Some(&body.mac),
+                    "Bind removed",
+                );
+                super::publish_status(&mut connection, super::StatusEventKind::Unbind, &body.mac);
                 HttpResponse::Ok().finish()
             }
             Err(err) => {
@@ -195,3 +791,114 @@ pub async fn remove_bind(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{body::MessageBody, http::StatusCode};
+    use diesel::{
+        dsl::{insert_into, update},
+        prelude::*,
+    };
+
+    use super::{BindRequestBody, bind_machine, upsert_bind};
+    use crate::server::schema::id_bind::dsl as id_bind_dsl;
+    use crate::server::schema::player::dsl as player_dsl;
+
+    const MAC: &str = "02:fc:00:00:00:01";
+
+    /// Roster with ID t1 already bound to [`MAC`] under certificate `cert-t1`
+    fn bound_seat() -> SqliteConnection {
+        let mut connection = crate::server::database::test_connection();
+        insert_into(player_dsl::player)
+            .values((
+                player_dsl::id.eq("t1"),
+                player_dsl::username.eq("team1"),
+                player_dsl::password.eq("secret"),
+            ))
+            .execute(&mut connection)
+            .unwrap();
+        insert_into(id_bind_dsl::id_bind)
+            .values((
+                id_bind_dsl::mac.eq(MAC),
+                id_bind_dsl::id.eq("t1"),
+                id_bind_dsl::ip.eq("10.0.0.1"),
+                id_bind_dsl::client_version.eq(""),
+                id_bind_dsl::last_seen.eq(""),
+                id_bind_dsl::cert_fingerprint.eq("cert-t1"),
+            ))
+            .execute(&mut connection)
+            .unwrap();
+        connection
+    }
+
+    fn rebind_request() -> BindRequestBody {
+        BindRequestBody {
+            mac: MAC.to_string(),
+            id: "t1".to_string(),
+            client_version: None,
+            hostname: None,
+            fingerprint: None,
+        }
+    }
+
+    #[test]
+    fn rebind_without_bound_certificate_gets_no_certificate() {
+        let mut connection = bound_seat();
+        for presented in [None, Some("cert-other")] {
+            let response = bind_machine(&mut connection, "10.0.0.2", &rebind_request(), presented);
+            assert_eq!(response.status(), StatusCode::ACCEPTED);
+            let body = response.into_body().try_into_bytes().unwrap();
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(body["reason"], "certificate_required");
+            assert!(body.get("cert").is_none());
+            assert!(body.get("key").is_none());
+        }
+
+        let cert_fingerprint = id_bind_dsl::id_bind
+            .find(MAC)
+            .select(id_bind_dsl::cert_fingerprint)
+            .first::<Option<String>>(&mut connection)
+            .unwrap();
+        assert_eq!(cert_fingerprint.as_deref(), Some("cert-t1"));
+    }
+
+    #[test]
+    fn rebind_keeps_the_machine_fingerprint_and_flags_another() {
+        let mut connection = bound_seat();
+        update(id_bind_dsl::id_bind.find(MAC))
+            .set((
+                id_bind_dsl::machine_fingerprint.eq("machine-t1"),
+                id_bind_dsl::reported_fingerprint.eq("machine-t1"),
+            ))
+            .execute(&mut connection)
+            .unwrap();
+
+        upsert_bind(
+            &mut connection,
+            MAC,
+            "t1",
+            "10.0.0.1",
+            "",
+            "cert-new",
+            Some("machine-other"),
+        )
+        .unwrap();
+        let fingerprints = id_bind_dsl::id_bind
+            .find(MAC)
+            .select((
+                id_bind_dsl::cert_fingerprint,
+                id_bind_dsl::machine_fingerprint,
+                id_bind_dsl::reported_fingerprint,
+            ))
+            .first::<(Option<String>, Option<String>, Option<String>)>(&mut connection)
+            .unwrap();
+        assert_eq!(
+            fingerprints,
+            (
+                Some("cert-new".to_string()),
+                Some("machine-t1".to_string()),
+                Some("machine-other".to_string())
+            )
+        );
+    }
+}
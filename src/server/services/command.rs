@@ -0,0 +1,258 @@
+use actix_web::{
+    HttpResponse, Responder, get, post,
+    web::{Json, Query},
+};
+use chrono::Utc;
+use diesel::{
+    dsl::{insert_into, update},
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+use tracing_unwrap::OptionExt;
+
+use crate::server::schema::command_queue::dsl as command_dsl;
+
+pub const STATUS_PENDING: &str = "pending";
+pub const STATUS_SUCCESS: &str = "success";
+pub const STATUS_FAILED: &str = "failed";
+
+/// Operations a client can be asked to run on its next heartbeat
+#[derive(Deserialize, Serialize, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum RemoteCommand {
+    Clean,
+    Sync,
+    TerminateSession,
+    AutologinSession,
+    LockSession,
+}
+
+impl RemoteCommand {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RemoteCommand::Clean => "clean",
+            RemoteCommand::Sync => "sync",
+            RemoteCommand::TerminateSession => "terminate_session",
+            RemoteCommand::AutologinSession => "autologin_session",
+            RemoteCommand::LockSession => "lock_session",
+        }
+    }
+
+    pub fn parse(command: &str) -> Option<Self> {
+        match command {
+            "clean" => Some(RemoteCommand::Clean),
+            "sync" => Some(RemoteCommand::Sync),
+            "terminate_session" => Some(RemoteCommand::TerminateSession),
+            "autologin_session" => Some(RemoteCommand::AutologinSession),
+            "lock_session" => Some(RemoteCommand::LockSession),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Queryable)]
+pub struct PendingCommand {
+    id: i32,
+    command: String,
+}
+
+#[derive(Deserialize)]
+pub struct CommandAck {
+    id: i32,
+    success: bool,
+    #[serde(default)]
+    message: String,
+}
+
+/// Queue one command for every given MAC, returning the inserted row count.
+/// `timeline_step` is set when the command is fired by the contest timeline.
+pub fn enqueue_commands(
+    connection: &mut SqliteConnection,
+    macs: &[String],
+    command: RemoteCommand,
+    timeline_step: Option<&str>,
+) -> QueryResult<usize> {
+    let timestamp = Utc::now().timestamp().to_string();
+    let rows = macs
+        .iter()
+        .map(|mac| {
+            (
+                command_dsl::mac.eq(mac),
+                command_dsl::command.eq(command.as_str()),
+                command_dsl::status.eq(STATUS_PENDING),
+                command_dsl::created_at.eq(&timestamp),
+                command_dsl::updated_at.eq(&timestamp),
+                command_dsl::timeline_step.eq(timeline_step),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    insert_into(command_dsl::command_queue)
+        .values(&rows)
+        .execute(connection)
+}
+
+/// Record command results reported by a client, only touching commands owned by that MAC
+pub fn ack_commands(
+    connection: &mut SqliteConnection,
+    mac: &str,
+    acks: &[CommandAck],
+) -> QueryResult<()> {
+    let timestamp = Utc::now().timestamp().to_string();
+    for ack in acks {
+        let status = if ack.success {
+            STATUS_SUCCESS
+        } else {
+            STATUS_FAILED
+        };
+        let affected = update(
+            command_dsl::command_queue
+                .filter(command_dsl::id.eq(ack.id))
+                .filter(command_dsl::mac.eq(mac))
+                .filter(command_dsl::status.eq(STATUS_PENDING)),
+        )
+        .set((
+            command_dsl::status.eq(status),
+            command_dsl::result.eq(&ack.message),
+            command_dsl::updated_at.eq(&timestamp),
+        ))
+        .execute(connection)?;
+
+        if affected == 0 {
+            tracing::warn!(
+                "MAC {} acknowledged unknown or finished command {}",
+                mac,
+                ack.id
+            );
+        } else {
+            tracing::info!("MAC {} command {} finished as {}", mac, ack.id, status);
+        }
+    }
+    Ok(())
+}
+
+/// Commands stay pending until acknowledged, so a lost response only delays them
+pub fn fetch_pending_commands(
+    connection: &mut SqliteConnection,
+    mac: &str,
+) -> QueryResult<Vec<PendingCommand>> {
+    command_dsl::command_queue
+        .filter(command_dsl::mac.eq(mac))
+        .filter(command_dsl::status.eq(STATUS_PENDING))
+        .order(command_dsl::id.asc())
+        .select((command_dsl::id, command_dsl::command))
+        .load::<PendingCommand>(connection)
+}
+
+#[derive(Deserialize)]
+struct QueueCommandRequestBody {
+    macs: Vec<String>,
+    command: RemoteCommand,
+}
+
+#[post("/command")]
+pub async fn queue_command(
+    auth: crate::server::services::Authenticated,
+    body: Json<QueueCommandRequestBody>,
+) -> impl Responder {
+    if body.macs.is_empty() {
+        return HttpResponse::BadRequest().body("No MAC provided");
+    }
+
+    let connection_pool = crate::server::database::DB_CONNECTION_POOL
+        .get()
+        .unwrap_or_log();
+
+    let mut connection;
+    match connection_pool.get() {
+        Ok(conn) => {
+            connection = conn;
+        }
+        Err(err) => {
+            tracing::error!("Error getting database connection {}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+
+    match enqueue_commands(&mut connection, &body.macs, body.command, None) {
+        Ok(count) => {
+            tracing::info!(
+                "Queued command {} for {} MAC(s)",
+                body.command.as_str(),
+                count
+            );
+            let actor = auth.actor();
+            for mac in &body.macs {
+                super::record_audit(
+                    &mut connection,
+                    super::AuditEvent::CommandQueued,
+                    &actor,
+                    Some(mac),
+                    body.command.as_str(),
+                );
+            }
+            HttpResponse::Ok().finish()
+        }
+        Err(err) => {
+            tracing::error!(
+                "Error queueing command {}, err {}",
+                body.command.as_str(),
+                err
+            );
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ListCommandQuery {
+    mac: Option<String>,
+}
+
+#[derive(Serialize, Queryable)]
+struct CommandInfo {
+    id: i32,
+    mac: String,
+    command: String,
+    status: String,
+    result: Option<String>,
+    created_at: String,
+    updated_at: String,
+    timeline_step: Option<String>,
+}
+
+#[get("/command")]
+pub async fn list_commands(
+    _auth: crate::server::services::Authenticated,
+    query: Query<ListCommandQuery>,
+) -> impl Responder {
+    let connection_pool = crate::server::database::DB_CONNECTION_POOL
+        .get()
+        .unwrap_or_log();
+
+    let mut connection;
+    match connection_pool.get() {
+        Ok(conn) => {
+            connection = conn;
+        }
+        Err(err) => {
+            tracing::error!("Error getting database connection {}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+
+    let mut statement = command_dsl::command_queue
+        .order(command_dsl::id.desc())
+        .into_boxed();
+    if let Some(mac) = &query.mac {
+        statement = statement.filter(command_dsl::mac.eq(mac));
+    }
+
+    match statement.load::<CommandInfo>(&mut connection) {
+        Ok(commands) => HttpResponse::Ok().json(commands),
+        Err(err) => {
+            tracing::error!("Error fetching command queue {}", err);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
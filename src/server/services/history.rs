@@ -0,0 +1,89 @@
+use actix_web::{HttpResponse, Responder, get, web::Query};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use tracing_unwrap::OptionExt;
+
+use crate::server::schema::heartbeat::dsl as heartbeat_dsl;
+use crate::server::schema::seat_event::dsl as seat_event_dsl;
+
+#[derive(Deserialize)]
+struct HistoryQuery {
+    mac: String,
+    /// Only return records at or after this unix timestamp
+    since: Option<i64>,
+}
+
+#[derive(Serialize, Queryable)]
+struct HeartbeatInfo {
+    ip: String,
+    received_at: i64,
+}
+
+#[derive(Serialize, Queryable)]
+struct SeatEventInfo {
+    event: String,
+    created_at: i64,
+}
+
+#[derive(Serialize)]
+struct HistoryResponse {
+    heartbeats: Vec<HeartbeatInfo>,
+    events: Vec<SeatEventInfo>,
+}
+
+#[get("/status/history")]
+pub async fn get_history(
+    _auth: crate::server::services::Authenticated,
+    query: Query<HistoryQuery>,
+) -> impl Responder {
+    let connection_pool = crate::server::database::DB_CONNECTION_POOL
+        .get()
+        .unwrap_or_log();
+
+    let mut connection;
+    match connection_pool.get() {
+        Ok(conn) => {
+            connection = conn;
+        }
+        Err(err) => {
+            tracing::error!("Error getting database connection {}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+
+    let since = query.since.unwrap_or(0);
+
+    let heartbeats = match heartbeat_dsl::heartbeat
+        .filter(heartbeat_dsl::mac.eq(&query.mac))
+        .filter(heartbeat_dsl::received_at.ge(since))
+        .order(heartbeat_dsl::received_at.asc())
+        .select((heartbeat_dsl::ip, heartbeat_dsl::received_at))
+        .load::<HeartbeatInfo>(&mut connection)
+    {
+        Ok(result) => result,
+        Err(err) => {
+            tracing::error!(
+                "Error fetching heartbeat history of MAC {}: {}",
+                query.mac,
+                err
+            );
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let events = match seat_event_dsl::seat_event
+        .filter(seat_event_dsl::mac.eq(&query.mac))
+        .filter(seat_event_dsl::created_at.ge(since))
+        .order(seat_event_dsl::created_at.asc())
+        .select((seat_event_dsl::event, seat_event_dsl::created_at))
+        .load::<SeatEventInfo>(&mut connection)
+    {
+        Ok(result) => result,
+        Err(err) => {
+            tracing::error!("Error fetching seat events of MAC {}: {}", query.mac, err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    HttpResponse::Ok().json(HistoryResponse { heartbeats, events })
+}
@@ -0,0 +1,586 @@
+use actix_web::{
+    HttpRequest, HttpResponse, Responder, get, post,
+    web::{Json, Query},
+};
+use chrono::Utc;
+use diesel::{
+    dsl::{delete, exists, insert_into, select, update},
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+use tracing_unwrap::OptionExt;
+
+use crate::server::schema::id_bind::dsl as id_bind_dsl;
+use crate::server::schema::pending_bind::dsl as pending_dsl;
+use crate::server::schema::player::dsl as player_dsl;
+
+pub const BIND_PENDING: &str = "pending";
+pub const BIND_APPROVED: &str = "approved";
+pub const BIND_REJECTED: &str = "rejected";
+
+/// Queue a conflicting bind for the panel, returning the request id and the secret the client
+/// polls the decision with. Binding again with the same MAC and ID refreshes the queued request
+/// and keeps its secret, so a client still polling with it is not cut off.
+pub(super) fn queue_bind(
+    connection: &mut SqliteConnection,
+    mac: &str,
+    bind_id: &str,
+    ip: &str,
+    client_version: &str,
+    reason: &str,
+    machine_fingerprint: Option<&str>,
+) -> QueryResult<(i32, String)> {
+    let timestamp = Utc::now().timestamp().to_string();
+
+    connection.transaction(|connection| {
+        let queued = pending_dsl::pending_bind
+            .filter(pending_dsl::mac.eq(mac))
+            .filter(pending_dsl::bind_id.eq(bind_id))
+            .filter(pending_dsl::status.eq(BIND_PENDING))
+            .select((pending_dsl::id, pending_dsl::secret))
+            .first::<(i32, String)>(connection)
+            .optional()?;
+
+        match queued {
+            Some((request_id, secret)) => {
+                update(pending_dsl::pending_bind.find(request_id))
+                    .set((
+                        pending_dsl::ip.eq(ip),
+                        pending_dsl::client_version.eq(client_version),
+                        pending_dsl::reason.eq(reason),
+                        pending_dsl::machine_fingerprint.eq(machine_fingerprint),
+                        pending_dsl::created_at.eq(&timestamp),
+                    ))
+                    .execute(connection)?;
+                Ok((request_id, secret))
+            }
+            None => {
+                let secret = hex::encode(rand::random::<[u8; 16]>());
+                insert_into(pending_dsl::pending_bind)
+                    .values((
+                        pending_dsl::mac.eq(mac),
+                        pending_dsl::bind_id.eq(bind_id),
+                        pending_dsl::ip.eq(ip),
+                        pending_dsl::client_version.eq(client_version),
+                        pending_dsl::reason.eq(reason),
+                        pending_dsl::machine_fingerprint.eq(machine_fingerprint),
+                        pending_dsl::secret.eq(&secret),
+                        pending_dsl::status.eq(BIND_PENDING),
+                        pending_dsl::created_at.eq(&timestamp),
+                    ))
+                    .execute(connection)?;
+                let request_id = pending_dsl::pending_bind
+                    .filter(pending_dsl::secret.eq(&secret))
+                    .select(pending_dsl::id)
+                    .first::<i32>(connection)?;
+                Ok((request_id, secret))
+            }
+        }
+    })
+}
+
+#[derive(Deserialize)]
+struct ListPendingBindQuery {
+    status: Option<String>,
+}
+
+#[derive(Serialize, Queryable)]
+struct PendingBindInfo {
+    id: i32,
+    mac: String,
+    bind_id: String,
+    ip: String,
+    client_version: String,
+    reason: String,
+    status: String,
+    created_at: String,
+    decided_at: Option<String>,
+}
+
+#[get("/bind/pending")]
+pub async fn list_pending_binds(
+    _auth: crate::server::services::Authenticated,
+    query: Query<ListPendingBindQuery>,
+) -> impl Responder {
+    let connection_pool = crate::server::database::DB_CONNECTION_POOL
+        .get()
+        .unwrap_or_log();
+
+    let mut connection;
+    match connection_pool.get() {
+        Ok(conn) => {
+            connection = conn;
+        }
+        Err(err) => {
+            tracing::error!("Error getting database connection {}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+
+    let mut statement = pending_dsl::pending_bind
+        .order(pending_dsl::id.desc())
+        .select((
+            pending_dsl::id,
+            pending_dsl::mac,
+            pending_dsl::bind_id,
+            pending_dsl::ip,
+            pending_dsl::client_version,
+            pending_dsl::reason,
+            pending_dsl::status,
+            pending_dsl::created_at,
+            pending_dsl::decided_at,
+        ))
+        .into_boxed();
+    if let Some(status) = &query.status {
+        statement = statement.filter(pending_dsl::status.eq(status));
+    }
+
+    match statement.load::<PendingBindInfo>(&mut connection) {
+        Ok(requests) => HttpResponse::Ok().json(requests),
+        Err(err) => {
+            tracing::error!("Error fetching pending binds {}", err);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct DecideBindRequestBody {
+    id: i32,
+}
+
+enum ApproveOutcome {
+    NotFound,
+    AlreadyDecided(String),
+    UnknownId(String),
+    Approved {
+        mac: String,
+        bind_id: String,
+        reason: String,
+        /// MACs the ID was bound to before, their certificates are revoked
+        replaced_macs: Vec<String>,
+    },
+}
+
+/// Bind the requesting MAC, unbinding whichever machine held the ID before
+fn approve_bind(
+    connection: &mut SqliteConnection,
+    request_id: i32,
+) -> anyhow::Result<ApproveOutcome> {
+    connection.transaction::<_, anyhow::Error, _>(|connection| {
+        let Some((mac, bind_id, ip, client_version, reason, machine_fingerprint, status)) =
+            pending_dsl::pending_bind
+                .find(request_id)
+                .select((
+                    pending_dsl::mac,
+                    pending_dsl::bind_id,
+                    pending_dsl::ip,
+                    pending_dsl::client_version,
+                    pending_dsl::reason,
+                    pending_dsl::machine_fingerprint,
+                    pending_dsl::status,
+                ))
+                .first::<(
+                    String,
+                    String,
+                    String,
+                    String,
+                    String,
+                    Option<String>,
+                    String,
+                )>(connection)
+                .optional()?
+        else {
+            return Ok(ApproveOutcome::NotFound);
+        };
+        if status != BIND_PENDING {
+            return Ok(ApproveOutcome::AlreadyDecided(status));
+        }
+        // The roster may have been reloaded since the request was queued
+        let player_exist = select(exists(
+            player_dsl::player
+                .filter(player_dsl::contest.eq_any(crate::server::contest::active_contest()))
+                .filter(player_dsl::id.eq(&bind_id)),
+        ))
+        .get_result::<bool>(connection)?;
+        if !player_exist {
+            return Ok(ApproveOutcome::UnknownId(bind_id));
+        }
+
+        let replaced_macs = id_bind_dsl::id_bind
+            .filter(id_bind_dsl::id.eq(&bind_id))
+            .filter(id_bind_dsl::mac.ne(&mac))
+            .select(id_bind_dsl::mac)
+            .load::<String>(connection)?;
+        delete(
+            id_bind_dsl::id_bind
+                .filter(id_bind_dsl::id.eq(&bind_id))
+                .filter(id_bind_dsl::mac.ne(&mac)),
+        )
+        .execute(connection)?;
+
+        let certificate = crate::server::pki::issue_client_certificate(&mac)?;
+        super::bind::upsert_bind(
+            connection,
+            &mac,
+            &bind_id,
+            &ip,
+            &client_version,
+            &certificate.fingerprint,
+            machine_fingerprint.as_deref(),
+        )?;
+
+        update(pending_dsl::pending_bind.find(request_id))
+            .set((
+                pending_dsl::status.eq(BIND_APPROVED),
+                pending_dsl::cert_pem.eq(Some(certificate.cert_pem)),
+                pending_dsl::key_pem.eq(Some(certificate.key_pem)),
+                pending_dsl::decided_at.eq(Some(Utc::now().timestamp().to_string())),
+            ))
+            .execute(connection)?;
+
+        Ok(ApproveOutcome::Approved {
+            mac,
+            bind_id,
+            reason,
+            replaced_macs,
+        })
+    })
+}
+
+#[post("/bind/pending/approve")]
+pub async fn approve_pending_bind(
+    auth: crate::server::services::Authenticated,
+    body: Json<DecideBindRequestBody>,
+) -> impl Responder {
+    let connection_pool = crate::server::database::DB_CONNECTION_POOL
+        .get()
+        .unwrap_or_log();
+
+    let mut connection;
+    match connection_pool.get() {
+        Ok(conn) => {
+            connection = conn;
+        }
+        Err(err) => {
+            tracing::error!("Error getting database connection {}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+
+    match approve_bind(&mut connection, body.id) {
+        Ok(ApproveOutcome::NotFound) => {
+            HttpResponse::NotFound().body(format!("No bind request {}", body.id))
+        }
+        Ok(ApproveOutcome::AlreadyDecided(status)) => {
+            HttpResponse::Conflict().body(format!("Bind request {} already {}", body.id, status))
+        }
+        Ok(ApproveOutcome::UnknownId(bind_id)) => HttpResponse::Conflict()
+            .body(format!("ID {} is no longer in the player roster", bind_id)),
+        Ok(ApproveOutcome::Approved {
+            mac,
+            bind_id,
+            reason,
+            replaced_macs,
+        }) => {
+            for replaced_mac in &replaced_macs {
+                tracing::warn!(
+                    "Unbinded MAC {} from ID {} for approved bind request {}",
+                    replaced_mac,
+                    bind_id,
+                    body.id
+                );
+                super::publish_status(
+                    &mut connection,
+                    super::StatusEventKind::Unbind,
+                    replaced_mac,
+                );
+            }
+            tracing::info!(
+                "Approved bind request {}, MAC {} bound to ID {}",
+                body.id,
+                mac,
+                bind_id
+            );
+            super::publish_status(&mut connection, super::StatusEventKind::Bind, &mac);
+            if reason == "mac_changed" {
+                super::record_audit(
+                    &mut connection,
+                    super::AuditEvent::MacChanged,
+                    &auth.actor(),
+                    Some(&mac),
+                    &format!(
+                        "Request {} moved ID {} from MAC(s) [{}] with the same machine fingerprint",
+                        body.id,
+                        bind_id,
+                        replaced_macs.join(", ")
+                    ),
+                );
+            }
+            super::record_audit(
+                &mut connection,
+                super::AuditEvent::PendingBindApproved,
+                &auth.actor(),
+                Some(&mac),
+                &format!(
+                    "Request {} bound ID {}, replaced MAC(s) [{}]",
+                    body.id,
+                    bind_id,
+                    replaced_macs.join(", ")
+                ),
+            );
+            HttpResponse::Ok().finish()
+        }
+        Err(err) => {
+            tracing::error!("Error approving bind request {}, err {:#}", body.id, err);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[post("/bind/pending/reject")]
+pub async fn reject_pending_bind(
+    auth: crate::server::services::Authenticated,
+    body: Json<DecideBindRequestBody>,
+) -> impl Responder {
+    let connection_pool = crate::server::database::DB_CONNECTION_POOL
+        .get()
+        .unwrap_or_log();
+
+    let mut connection;
+    match connection_pool.get() {
+        Ok(conn) => {
+            connection = conn;
+        }
+        Err(err) => {
+            tracing::error!("Error getting database connection {}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+
+    match update(
+        pending_dsl::pending_bind
+            .filter(pending_dsl::id.eq(body.id))
+            .filter(pending_dsl::status.eq(BIND_PENDING)),
+    )
+    .set((
+        pending_dsl::status.eq(BIND_REJECTED),
+        pending_dsl::decided_at.eq(Some(Utc::now().timestamp().to_string())),
+    ))
+    .execute(&mut connection)
+    {
+        Ok(0) => HttpResponse::NotFound().body(format!("No pending bind request {}", body.id)),
+        Ok(_) => {
+            tracing::info!("Rejected bind request {}", body.id);
+            super::record_audit(
+                &mut connection,
+                super::AuditEvent::PendingBindRejected,
+                &auth.actor(),
+                None,
+                &format!("Request {}", body.id),
+            );
+            HttpResponse::Ok().finish()
+        }
+        Err(err) => {
+            tracing::error!("Error rejecting bind request {}, err {}", body.id, err);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct BindDecisionRequestBody {
+    request_id: i32,
+    secret: String,
+}
+
+#[derive(Serialize)]
+struct BindDecisionResponseBody {
+    status: String,
+    /// Issued client certificate, only delivered once after approval
+    cert: Option<String>,
+    key: Option<String>,
+}
+
+#[post("/bind/decision")]
+pub async fn poll_bind_decision(
+    req: HttpRequest,
+    body: Json<BindDecisionRequestBody>,
+) -> impl Responder {
+    let connection_pool = crate::server::database::DB_CONNECTION_POOL
+        .get()
+        .unwrap_or_log();
+
+    let mut connection;
+    match connection_pool.get() {
+        Ok(conn) => {
+            connection = conn;
+        }
+        Err(err) => {
+            tracing::error!("Error getting database connection {}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+
+    let request = match pending_dsl::pending_bind
+        .filter(pending_dsl::id.eq(body.request_id))
+        .filter(pending_dsl::secret.eq(&body.secret))
+        .select((
+            pending_dsl::mac,
+            pending_dsl::status,
+            pending_dsl::cert_pem,
+            pending_dsl::key_pem,
+        ))
+        .first::<(String, String, Option<String>, Option<String>)>(&mut connection)
+        .optional()
+    {
+        Ok(Some(request)) => request,
+        Ok(None) => {
+            return HttpResponse::NotFound().body(format!("No bind request {}", body.request_id));
+        }
+        Err(err) => {
+            tracing::error!(
+                "Error fetching bind request {}, err {}",
+                body.request_id,
+                err
+            );
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let (mac, status, cert, key) = request;
+    match (status, cert, key) {
+        (status, Some(cert), Some(key)) if status == BIND_APPROVED => {
+            // The key is only kept until the client picked it up
+            if let Err(err) = update(pending_dsl::pending_bind.find(body.request_id))
+                .set((
+                    pending_dsl::cert_pem.eq(None::<String>),
+                    pending_dsl::key_pem.eq(None::<String>),
+                ))
+                .execute(&mut connection)
+            {
+                tracing::error!(
+                    "Error clearing certificate of bind request {}, err {}",
+                    body.request_id,
+                    err
+                );
+                return HttpResponse::InternalServerError().finish();
+            }
+            super::record_audit(
+                &mut connection,
+                super::AuditEvent::BindDecisionDelivered,
+                &super::AuditActor::client(
+                    Some(mac),
+                    req.peer_addr().map(|addr| addr.ip().to_string()),
+                ),
+                None,
+                &format!("Certificate of request {} delivered", body.request_id),
+            );
+            HttpResponse::Ok().json(BindDecisionResponseBody {
+                status,
+                cert: Some(cert),
+                key: Some(key),
+            })
+        }
+        (status, _, _) if status == BIND_APPROVED => HttpResponse::Gone().body(format!(
+            "Certificate of bind request {} already delivered",
+            body.request_id
+        )),
+        (status, _, _) => HttpResponse::Ok().json(BindDecisionResponseBody {
+            status,
+            cert: None,
+            key: None,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use diesel::{dsl::insert_into, prelude::*};
+
+    use super::{ApproveOutcome, approve_bind, queue_bind};
+    use crate::server::schema::id_bind::dsl as id_bind_dsl;
+    use crate::server::schema::player::dsl as player_dsl;
+
+    #[test]
+    fn queueing_again_keeps_the_secret() {
+        let mut connection = crate::server::database::test_connection();
+        let first = queue_bind(
+            &mut connection,
+            "02:fc:00:00:00:01",
+            "t1",
+            "10.0.0.1",
+            "",
+            "id_bound",
+            None,
+        )
+        .unwrap();
+        let again = queue_bind(
+            &mut connection,
+            "02:fc:00:00:00:01",
+            "t1",
+            "10.0.0.2",
+            "",
+            "id_bound",
+            None,
+        )
+        .unwrap();
+        assert_eq!(first, again);
+    }
+
+    #[test]
+    fn approved_mac_change_keeps_the_machine_fingerprint() {
+        crate::server::pki::init_test_issuer();
+        let mut connection = crate::server::database::test_connection();
+        insert_into(player_dsl::player)
+            .values((
+                player_dsl::id.eq("t1"),
+                player_dsl::username.eq("team1"),
+                player_dsl::password.eq("secret"),
+            ))
+            .execute(&mut connection)
+            .unwrap();
+        insert_into(id_bind_dsl::id_bind)
+            .values((
+                id_bind_dsl::mac.eq("02:fc:00:00:00:01"),
+                id_bind_dsl::id.eq("t1"),
+                id_bind_dsl::ip.eq("10.0.0.1"),
+                id_bind_dsl::client_version.eq(""),
+                id_bind_dsl::last_seen.eq(""),
+                id_bind_dsl::machine_fingerprint.eq("machine-t1"),
+                id_bind_dsl::reported_fingerprint.eq("machine-t1"),
+            ))
+            .execute(&mut connection)
+            .unwrap();
+
+        let (request_id, _) = queue_bind(
+            &mut connection,
+            "02:fc:00:00:00:02",
+            "t1",
+            "10.0.0.1",
+            "",
+            "mac_changed",
+            Some("machine-t1"),
+        )
+        .unwrap();
+        let outcome = approve_bind(&mut connection, request_id).unwrap();
+        assert!(matches!(outcome, ApproveOutcome::Approved { .. }));
+
+        let binds = id_bind_dsl::id_bind
+            .select((
+                id_bind_dsl::mac,
+                id_bind_dsl::machine_fingerprint,
+                id_bind_dsl::reported_fingerprint,
+            ))
+            .load::<(String, Option<String>, Option<String>)>(&mut connection)
+            .unwrap();
+        assert_eq!(
+            binds,
+            vec![(
+                "02:fc:00:00:00:02".to_string(),
+                Some("machine-t1".to_string()),
+                Some("machine-t1".to_string())
+            )]
+        );
+    }
+}
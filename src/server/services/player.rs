@@ -0,0 +1,217 @@
+use actix_web::{HttpResponse, Responder, post, web::Json};
+use diesel::{dsl::update, prelude::*};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use tracing_unwrap::OptionExt;
+
+use super::command::{RemoteCommand, enqueue_commands};
+use crate::server::contest::active_contest;
+use crate::server::schema::id_bind::dsl as id_bind_dsl;
+use crate::server::schema::player::dsl as player_dsl;
+
+/// Generated passwords leave out characters that are easy to mistype, like 0/O and 1/l
+const PASSWORD_ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyzABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+const PASSWORD_LENGTH: usize = 12;
+
+fn generate_password() -> String {
+    let mut rng = rand::rng();
+    (0..PASSWORD_LENGTH)
+        .map(|_| PASSWORD_ALPHABET[rng.random_range(0..PASSWORD_ALPHABET.len())] as char)
+        .collect()
+}
+
+#[derive(Deserialize)]
+struct SetPasswordRequestBody {
+    id: String,
+    /// New password, a random one is generated when absent
+    #[serde(default)]
+    password: Option<String>,
+}
+
+#[derive(Serialize)]
+struct SetPasswordResponseBody {
+    id: String,
+    password: String,
+    /// Bound seats that got a sync queued
+    macs: Vec<String>,
+}
+
+enum SetPasswordOutcome {
+    Updated(Vec<String>),
+    UnknownId,
+}
+
+/// Change the password of a player in the active contest, bound seats resync on their next heartbeat
+#[post("/player/password")]
+pub async fn set_player_password(
+    auth: crate::server::services::Authenticated,
+    body: Json<SetPasswordRequestBody>,
+) -> impl Responder {
+    let password = match &body.password {
+        Some(password) if password.is_empty() => {
+            return HttpResponse::BadRequest().body("Password must not be empty");
+        }
+        Some(password) => password.clone(),
+        None => generate_password(),
+    };
+    let encrypted_password = match crate::server::credential::encrypt_password(&password) {
+        Ok(encrypted_password) => encrypted_password,
+        Err(err) => {
+            tracing::error!("Error encrypting password of ID {}, err {:#}", body.id, err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let connection_pool = crate::server::database::DB_CONNECTION_POOL
+        .get()
+        .unwrap_or_log();
+
+    let mut connection;
+    match connection_pool.get() {
+        Ok(conn) => {
+            connection = conn;
+        }
+        Err(err) => {
+            tracing::error!("Error getting database connection {}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+
+    let result = connection.transaction::<_, diesel::result::Error, _>(|connection| {
+        let affected = update(
+            player_dsl::player
+                .filter(player_dsl::contest.eq_any(active_contest()))
+                .filter(player_dsl::id.eq(&body.id)),
+        )
+        .set((
+            player_dsl::password.eq(&encrypted_password),
+            player_dsl::synced.eq(false as i32),
+        ))
+        .execute(connection)?;
+        if affected == 0 {
+            return Ok(SetPasswordOutcome::UnknownId);
+        }
+
+        let macs = id_bind_dsl::id_bind
+            .filter(id_bind_dsl::id.eq(&body.id))
+            .select(id_bind_dsl::mac)
+            .load::<String>(connection)?;
+        if !macs.is_empty() {
+            enqueue_commands(connection, &macs, RemoteCommand::Sync, None)?;
+        }
+        Ok(SetPasswordOutcome::Updated(macs))
+    });
+
+    match result {
+        Ok(SetPasswordOutcome::Updated(macs)) => {
+            tracing::info!(
+                "Password of ID {} changed, sync queued for {} seat(s)",
+                body.id,
+                macs.len()
+            );
+            for mac in &macs {
+                super::publish_status(&mut connection, super::StatusEventKind::Password, mac);
+            }
+            super::record_audit(
+                &mut connection,
+                super::AuditEvent::PasswordChanged,
+                &auth.actor(),
+                Some(&body.id),
+                &format!("Sync queued for MAC(s) [{}]", macs.join(", ")),
+            );
+            HttpResponse::Ok().json(SetPasswordResponseBody {
+                id: body.id.clone(),
+                password,
+                macs,
+            })
+        }
+        Ok(SetPasswordOutcome::UnknownId) => {
+            HttpResponse::NotFound().body(format!("ID {} is not in the active contest", body.id))
+        }
+        Err(err) => {
+            tracing::error!("Error changing password of ID {}, err {}", body.id, err);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct RevealPasswordRequestBody {
+    id: String,
+}
+
+#[derive(Serialize)]
+struct RevealPasswordResponseBody {
+    id: String,
+    username: String,
+    password: String,
+}
+
+/// Passwords are redacted from `/status`, staff fetch a single one here and every reveal is logged
+#[post("/player/password/reveal")]
+pub async fn reveal_player_password(
+    auth: crate::server::services::Authenticated,
+    body: Json<RevealPasswordRequestBody>,
+) -> impl Responder {
+    let connection_pool = crate::server::database::DB_CONNECTION_POOL
+        .get()
+        .unwrap_or_log();
+
+    let mut connection;
+    match connection_pool.get() {
+        Ok(conn) => {
+            connection = conn;
+        }
+        Err(err) => {
+            tracing::error!("Error getting database connection {}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+
+    let (username, password) = match player_dsl::player
+        .filter(player_dsl::contest.eq_any(active_contest()))
+        .filter(player_dsl::id.eq(&body.id))
+        .select((player_dsl::username, player_dsl::password))
+        .first::<(String, String)>(&mut connection)
+        .optional()
+    {
+        Ok(Some(result)) => result,
+        Ok(None) => {
+            return HttpResponse::NotFound()
+                .body(format!("ID {} is not in the active contest", body.id));
+        }
+        Err(err) => {
+            tracing::error!("Error fetching from database {}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+    let password = match crate::server::credential::decrypt_password(&password) {
+        Ok(password) => password,
+        Err(err) => {
+            tracing::error!(
+                "Failed to decrypt password of ID {}, err {:#}",
+                body.id,
+                err
+            );
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    tracing::warn!(
+        "Password of ID {} revealed to panel at {}",
+        body.id,
+        auth.ip.as_deref().unwrap_or("unknown")
+    );
+    super::record_audit(
+        &mut connection,
+        super::AuditEvent::PasswordRevealed,
+        &auth.actor(),
+        Some(&body.id),
+        &format!("Password of user {} revealed", username),
+    );
+    HttpResponse::Ok().json(RevealPasswordResponseBody {
+        id: body.id.clone(),
+        username,
+        password,
+    })
+}
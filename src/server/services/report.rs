@@ -1,23 +1,168 @@
 use actix_web::{HttpRequest, HttpResponse, Responder, post, web::Json};
 use chrono::Utc;
-use diesel::dsl::{count_star, insert_into, update};
+use diesel::dsl::{count_star, insert_into, replace_into, update};
 use diesel::prelude::*;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use tracing_unwrap::OptionExt;
 
+use super::command::{self, CommandAck, PendingCommand};
 use crate::server::schema::id_bind::dsl as id_bind_dsl;
 use crate::server::schema::player::dsl as player_dsl;
+use crate::server::schema::telemetry::dsl as telemetry_dsl;
 
 #[derive(Deserialize)]
 struct ReportStatusRequest {
     mac: String,
+    #[serde(default)]
+    fingerprint: Option<String>,
     synced: bool,
     #[serde(default)]
     client_version: Option<String>,
+    /// Reason of a rolled back sync
+    #[serde(default)]
+    sync_error: Option<String>,
+    /// Caddy config drift the client found and resynced
+    #[serde(default)]
+    drift: Option<String>,
+    /// Results of commands delivered on previous heartbeats
+    #[serde(default)]
+    acks: Vec<CommandAck>,
+    #[serde(default)]
+    telemetry: Option<TelemetryReport>,
+}
+
+#[derive(Deserialize)]
+struct TelemetryReport {
+    caddy_active: bool,
+    graphical_session: bool,
+    uptime_secs: Option<i64>,
+    load_avg: Option<f64>,
+    home_free_bytes: Option<i64>,
+    timestamp_ms: i64,
+    hostname: Option<String>,
+}
+
+/// Keep only the latest snapshot per MAC
+fn store_telemetry(
+    connection: &mut SqliteConnection,
+    mac: &str,
+    telemetry: &TelemetryReport,
+) -> QueryResult<usize> {
+    let now = Utc::now();
+    // Network latency is included, which is fine for spotting a drifting clock
+    let clock_offset_ms = telemetry.timestamp_ms - now.timestamp_millis();
+    replace_into(telemetry_dsl::telemetry)
+        .values((
+            telemetry_dsl::mac.eq(mac),
+            telemetry_dsl::caddy_active.eq(telemetry.caddy_active),
+            telemetry_dsl::graphical_session.eq(telemetry.graphical_session),
+            telemetry_dsl::uptime_secs.eq(telemetry.uptime_secs),
+            telemetry_dsl::load_avg.eq(telemetry.load_avg),
+            telemetry_dsl::home_free_bytes.eq(telemetry.home_free_bytes),
+            telemetry_dsl::clock_offset_ms.eq(clock_offset_ms),
+            telemetry_dsl::hostname.eq(&telemetry.hostname),
+            telemetry_dsl::updated_at.eq(now.timestamp().to_string()),
+        ))
+        .execute(connection)
+}
+
+/// A reported drift is kept so the panel keeps highlighting the seat, until a synced report
+/// without drift shows the machine runs the synced config again
+fn update_caddy_drift(
+    connection: &mut SqliteConnection,
+    mac: &str,
+    drift: Option<&str>,
+    synced: bool,
+    timestamp: &str,
+    actor: &super::AuditActor,
+) -> QueryResult<()> {
+    match drift {
+        Some(drift) => {
+            tracing::warn!("MAC {} reported Caddy drift: {}", mac, drift);
+            update(id_bind_dsl::id_bind.filter(id_bind_dsl::mac.eq(mac)))
+                .set((
+                    id_bind_dsl::caddy_drift.eq(drift),
+                    id_bind_dsl::caddy_drift_at.eq(timestamp),
+                ))
+                .execute(connection)?;
+            super::record_audit(
+                connection,
+                super::AuditEvent::CaddyDrift,
+                actor,
+                None,
+                drift,
+            );
+        }
+        None if synced => {
+            let cleared = update(
+                id_bind_dsl::id_bind
+                    .filter(id_bind_dsl::mac.eq(mac))
+                    .filter(id_bind_dsl::caddy_drift.is_not_null()),
+            )
+            .set((
+                id_bind_dsl::caddy_drift.eq(None::<String>),
+                id_bind_dsl::caddy_drift_at.eq(None::<String>),
+            ))
+            .execute(connection)?;
+            if cleared > 0 {
+                tracing::info!("MAC {} synced again, Caddy drift cleared", mac);
+            }
+        }
+        None => {}
+    }
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct ReportStatusResponse {
+    commands: Vec<PendingCommand>,
+}
+
+fn command_response(
+    connection: &mut SqliteConnection,
+    report: &ReportStatusRequest,
+) -> HttpResponse {
+    if let Err(err) = command::ack_commands(connection, &report.mac, &report.acks) {
+        tracing::error!(
+            "Error acknowledging commands of MAC {}, err {}",
+            report.mac,
+            err
+        );
+        return HttpResponse::InternalServerError().finish();
+    }
+    super::publish_status(connection, super::StatusEventKind::Report, &report.mac);
+
+    match command::fetch_pending_commands(connection, &report.mac) {
+        Ok(commands) => {
+            if !commands.is_empty() {
+                tracing::info!(
+                    "Delivering {} pending command(s) to MAC {}",
+                    commands.len(),
+                    report.mac
+                );
+            }
+            HttpResponse::Ok().json(ReportStatusResponse { commands })
+        }
+        Err(err) => {
+            tracing::error!(
+                "Error fetching pending commands of MAC {}, err {}",
+                report.mac,
+                err
+            );
+            HttpResponse::InternalServerError().finish()
+        }
+    }
 }
 
 #[post("/report")]
-pub async fn report_status(req: HttpRequest, report: Json<ReportStatusRequest>) -> impl Responder {
+pub async fn report_status(
+    req: HttpRequest,
+    identity: super::ClientIdentity,
+    report: Json<ReportStatusRequest>,
+) -> impl Responder {
+    let mut report = report.into_inner();
+    report.mac = identity.resolve(&report.mac);
+
     let client_ip;
     if let Some(value) = req.peer_addr() {
         client_ip = value.ip().to_string();
@@ -69,6 +214,13 @@ pub async fn report_status(req: HttpRequest, report: Json<ReportStatusRequest>)
         }
     }
 
+    if let Some(telemetry) = &report.telemetry
+        && let Err(err) = store_telemetry(&mut connection, &report.mac, telemetry)
+    {
+        tracing::error!("Error storing telemetry of MAC {}, err {}", report.mac, err);
+        return HttpResponse::InternalServerError().finish();
+    }
+
     let timestamp = Utc::now().timestamp().to_string();
     if insert_unknown {
         match insert_into(id_bind_dsl::id_bind)
@@ -84,7 +236,16 @@ pub async fn report_status(req: HttpRequest, report: Json<ReportStatusRequest>)
         {
             Ok(_) => {
                 tracing::info!("Logging unbinded MAC with ID as unknown");
-                return HttpResponse::Ok().finish();
+                super::record_audit(
+                    &mut connection,
+                    super::AuditEvent::UnknownMacReport,
+                    &super::AuditActor::client(Some(report.mac.clone()), Some(client_ip.clone())),
+                    None,
+                    &format!(
+                        "Unbound MAC reported with version '{}'",
+                        report.client_version.as_deref().unwrap_or_default()
+                    ),
+                );
             }
             Err(err) => {
                 tracing::error!("Failed to log unbinded MAC with ID as unknown, err {}", err);
@@ -95,28 +256,100 @@ pub async fn report_status(req: HttpRequest, report: Json<ReportStatusRequest>)
     }
 
     // Update client IP addr
-    match update(id_bind_dsl::id_bind.filter(id_bind_dsl::mac.eq(&report.mac)))
-        .set((
-            id_bind_dsl::ip.eq(&client_ip),
-            id_bind_dsl::client_version.eq(&report.client_version.as_deref().unwrap_or_default()),
-            id_bind_dsl::last_seen.eq(&timestamp),
-        ))
-        .execute(&mut connection)
+    if !insert_unknown {
+        match update(id_bind_dsl::id_bind.filter(id_bind_dsl::mac.eq(&report.mac)))
+            .set((
+                id_bind_dsl::ip.eq(&client_ip),
+                id_bind_dsl::client_version
+                    .eq(&report.client_version.as_deref().unwrap_or_default()),
+                id_bind_dsl::last_seen.eq(&timestamp),
+            ))
+            .execute(&mut connection)
+        {
+            Ok(_) => {}
+            Err(err) => {
+                tracing::error!(
+                    "Error updating IP {} with MAC {}, err {}",
+                    client_ip,
+                    report.mac,
+                    err
+                );
+                return HttpResponse::InternalServerError().finish();
+            }
+        }
+    }
+
+    // After last_seen so the offline sweep never sees a fresh heartbeat with a stale last_seen
+    if let Err(err) =
+        crate::server::presence::record_heartbeat(&mut connection, &report.mac, &client_ip)
     {
-        Ok(_) => {}
-        Err(err) => {
-            tracing::error!(
-                "Error updating IP {} with MAC {}, err {}",
-                client_ip,
-                report.mac,
-                err
-            );
-            return HttpResponse::InternalServerError().finish();
+        tracing::error!(
+            "Error recording heartbeat of MAC {}, err {}",
+            report.mac,
+            err
+        );
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    if insert_unknown {
+        return command_response(&mut connection, &report);
+    }
+
+    let actor = super::AuditActor::client(Some(report.mac.clone()), Some(client_ip.clone()));
+    if let Err(err) = super::bind::check_fingerprint(
+        &mut connection,
+        &report.mac,
+        report.fingerprint.as_deref(),
+        &actor,
+    ) {
+        tracing::error!(
+            "Error checking machine fingerprint of MAC {}, err {}",
+            report.mac,
+            err
+        );
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    if let Err(err) = update_caddy_drift(
+        &mut connection,
+        &report.mac,
+        report.drift.as_deref(),
+        report.synced,
+        &timestamp,
+        &actor,
+    ) {
+        tracing::error!(
+            "Error updating Caddy drift with MAC {}, err {}",
+            report.mac,
+            err
+        );
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    if let Some(sync_error) = &report.sync_error {
+        tracing::warn!("MAC {} reported sync failure: {}", report.mac, sync_error);
+    }
+    // A successful sync clears the last failure
+    if report.synced || report.sync_error.is_some() {
+        match update(id_bind_dsl::id_bind.filter(id_bind_dsl::mac.eq(&report.mac)))
+            .set(id_bind_dsl::sync_error.eq(&report.sync_error))
+            .execute(&mut connection)
+        {
+            Ok(_) => {}
+            Err(err) => {
+                tracing::error!(
+                    "Error updating sync error with MAC {}, err {}",
+                    report.mac,
+                    err
+                );
+                return HttpResponse::InternalServerError().finish();
+            }
         }
     }
 
     if report.synced {
         match update(player_dsl::player)
+            .filter(player_dsl::contest.eq_any(crate::server::contest::active_contest()))
             .filter(
                 player_dsl::id.eq_any(
                     id_bind_dsl::id_bind
@@ -137,5 +370,63 @@ pub async fn report_status(req: HttpRequest, report: Json<ReportStatusRequest>)
 
     tracing::info!("MAC {} heartbeat received!", report.mac);
 
-    HttpResponse::Ok().finish()
+    command_response(&mut connection, &report)
+}
+
+#[cfg(test)]
+mod tests {
+    use diesel::{dsl::insert_into, prelude::*};
+
+    use super::update_caddy_drift;
+    use crate::server::schema::id_bind::dsl as id_bind_dsl;
+    use crate::server::schema::player::dsl as player_dsl;
+    use crate::server::services::AuditActor;
+
+    const MAC: &str = "02:fc:00:00:00:01";
+
+    fn caddy_drift(connection: &mut SqliteConnection) -> (Option<String>, Option<String>) {
+        id_bind_dsl::id_bind
+            .select((id_bind_dsl::caddy_drift, id_bind_dsl::caddy_drift_at))
+            .filter(id_bind_dsl::mac.eq(MAC))
+            .first(connection)
+            .unwrap()
+    }
+
+    #[test]
+    fn synced_report_after_heal_clears_drift() {
+        let mut connection = crate::server::database::test_connection();
+        insert_into(player_dsl::player)
+            .values((
+                player_dsl::id.eq("t1"),
+                player_dsl::username.eq("team1"),
+                player_dsl::password.eq("secret"),
+            ))
+            .execute(&mut connection)
+            .unwrap();
+        insert_into(id_bind_dsl::id_bind)
+            .values((
+                id_bind_dsl::mac.eq(MAC),
+                id_bind_dsl::id.eq("t1"),
+                id_bind_dsl::ip.eq("10.0.0.1"),
+                id_bind_dsl::client_version.eq(""),
+                id_bind_dsl::last_seen.eq(""),
+            ))
+            .execute(&mut connection)
+            .unwrap();
+        let actor = AuditActor::client(Some(MAC.to_string()), Some("10.0.0.1".to_string()));
+
+        let drift = "Caddy service is not active";
+        update_caddy_drift(&mut connection, MAC, Some(drift), false, "100", &actor).unwrap();
+        assert_eq!(
+            caddy_drift(&mut connection),
+            (Some(drift.to_string()), Some("100".to_string()))
+        );
+
+        // Plain heartbeats say nothing about the drift
+        update_caddy_drift(&mut connection, MAC, None, false, "110", &actor).unwrap();
+        assert_eq!(caddy_drift(&mut connection).0.as_deref(), Some(drift));
+
+        update_caddy_drift(&mut connection, MAC, None, true, "120", &actor).unwrap();
+        assert_eq!(caddy_drift(&mut connection), (None, None));
+    }
 }
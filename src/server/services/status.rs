@@ -1,30 +1,238 @@
+use std::collections::HashMap;
+
 use actix_web::{HttpResponse, Responder, get};
 use diesel::{
-    ExpressionMethods, JoinOnDsl, NullableExpressionMethods, QueryDsl, RunQueryDsl,
-    dsl::count_star, prelude::Queryable,
+    BoolExpressionMethods, ExpressionMethods, JoinOnDsl, NullableExpressionMethods,
+    OptionalExtension, QueryDsl, QueryResult, RunQueryDsl, SqliteConnection, dsl::count_star,
+    prelude::Queryable,
 };
 use serde::Serialize;
 use tracing_unwrap::OptionExt;
 
+use crate::server::contest::active_contest;
+
 #[derive(Serialize)]
 struct StatusResponse {
+    /// Contest profile served by sync
+    contest: Option<String>,
     bind_count: i64,
     info_count: i64,
     sync_count: i64,
     notsync_count: i64,
     infos: Vec<Info>,
+    /// Whole seat map, so the floor grid also shows seats without a bound machine
+    seats: Vec<SeatInfo>,
 }
 
-#[derive(Serialize, Queryable)]
-struct Info {
+#[derive(Serialize)]
+pub(super) struct Info {
     mac: Option<String>,
     id: String,
     ip: Option<String>,
     client_version: Option<String>,
     last_seen: Option<String>,
     username: Option<String>,
-    password: Option<String>,
+    team_name: Option<String>,
+    organization: Option<String>,
+    location: Option<String>,
     synced: Option<bool>,
+    /// Whether the server still receives heartbeats from this seat
+    online: Option<bool>,
+    /// Times the seat went offline within the flap window
+    offline_count: i64,
+    /// Offline count reached the flap limit, the seat keeps dropping in and out
+    flapping: bool,
+    /// Reason of the last rolled back sync
+    sync_error: Option<String>,
+    /// Last contest timeline step this seat finished
+    phase: Option<String>,
+    /// Latest heartbeat telemetry snapshot
+    telemetry: Option<TelemetryInfo>,
+    /// Seat map entry of the current IP
+    seat: Option<SeatInfo>,
+    /// Reported hostname differs from the seat at this IP, the machine was likely swapped
+    seat_mismatch: bool,
+    /// Machine fingerprint reported by this MAC differs from the one recorded at bind
+    fingerprint_mismatch: bool,
+    /// Last Caddy config drift the client found and resynced since bind
+    caddy_drift: Option<String>,
+    caddy_drift_at: Option<String>,
+}
+
+#[derive(Serialize, Queryable, Clone)]
+pub(super) struct SeatInfo {
+    ip: String,
+    room: String,
+    row: i32,
+    col: i32,
+    hostname: String,
+    /// Contest ID assigned to this seat for auto bind
+    id: Option<String>,
+}
+
+#[derive(Serialize, Queryable)]
+struct TelemetryInfo {
+    #[serde(skip)]
+    mac: String,
+    caddy_active: bool,
+    graphical_session: bool,
+    uptime_secs: Option<i64>,
+    load_avg: Option<f64>,
+    home_free_bytes: Option<i64>,
+    clock_offset_ms: i64,
+    hostname: Option<String>,
+    updated_at: String,
+}
+
+/// Load bound seats joined with their player, phase and telemetry, optionally for a single MAC
+pub(super) fn load_bound_infos(
+    connection: &mut SqliteConnection,
+    mac: Option<&str>,
+) -> QueryResult<Vec<Info>> {
+    use crate::server::schema::command_queue::dsl as command_dsl;
+    use crate::server::schema::id_bind::dsl as id_bind_dsl;
+    use crate::server::schema::player::dsl as player_dsl;
+    use crate::server::schema::seat::dsl as seat_dsl;
+    use crate::server::schema::telemetry::dsl as telemetry_dsl;
+
+    let mut statement = id_bind_dsl::id_bind
+        .left_outer_join(
+            player_dsl::player.on(id_bind_dsl::id
+                .eq(player_dsl::id)
+                .and(player_dsl::contest.eq_any(active_contest()))),
+        )
+        .select((
+            id_bind_dsl::mac.nullable(),
+            id_bind_dsl::id,
+            id_bind_dsl::ip.nullable(),
+            id_bind_dsl::client_version.nullable(),
+            id_bind_dsl::last_seen.nullable(),
+            player_dsl::username.nullable(),
+            player_dsl::synced.nullable(),
+            id_bind_dsl::sync_error.nullable(),
+            id_bind_dsl::online.nullable(),
+            player_dsl::team_name.nullable(),
+            player_dsl::organization.nullable(),
+            player_dsl::location.nullable(),
+            id_bind_dsl::machine_fingerprint.nullable(),
+            id_bind_dsl::reported_fingerprint.nullable(),
+            id_bind_dsl::caddy_drift.nullable(),
+            id_bind_dsl::caddy_drift_at.nullable(),
+        ))
+        .into_boxed();
+    if let Some(mac) = mac {
+        statement = statement.filter(id_bind_dsl::mac.eq(mac));
+    }
+    let mut infos = statement
+        .load::<(
+            Option<String>,
+            String,
+            Option<String>,
+            Option<String>,
+            Option<String>,
+            Option<String>,
+            Option<i32>,
+            Option<String>,
+            Option<bool>,
+            Option<String>,
+            Option<String>,
+            Option<String>,
+            Option<String>,
+            Option<String>,
+            Option<String>,
+            Option<String>,
+        )>(connection)?
+        .into_iter()
+        .map(|x| Info {
+            mac: x.0,
+            id: x.1,
+            ip: x.2,
+            client_version: x.3,
+            last_seen: x.4,
+            username: x.5,
+            team_name: x.9,
+            organization: x.10,
+            location: x.11,
+            synced: x.6.map(|i| i % 2 != 0),
+            online: x.8,
+            offline_count: 0,
+            flapping: false,
+            sync_error: x.7,
+            phase: None,
+            telemetry: None,
+            seat: None,
+            seat_mismatch: false,
+            fingerprint_mismatch: x.12.is_some() && x.13.is_some() && x.12 != x.13,
+            caddy_drift: x.14,
+            caddy_drift_at: x.15,
+        })
+        .collect::<Vec<Info>>();
+
+    // Ordered by id so the latest finished step of each MAC wins
+    let mut phase_statement = command_dsl::command_queue
+        .filter(command_dsl::timeline_step.is_not_null())
+        .filter(command_dsl::status.eq(super::command::STATUS_SUCCESS))
+        .order(command_dsl::id.asc())
+        .select((
+            command_dsl::mac,
+            command_dsl::timeline_step.assume_not_null(),
+        ))
+        .into_boxed();
+    let mut telemetry_statement = telemetry_dsl::telemetry.into_boxed();
+    if let Some(mac) = mac {
+        phase_statement = phase_statement.filter(command_dsl::mac.eq(mac));
+        telemetry_statement = telemetry_statement.filter(telemetry_dsl::mac.eq(mac));
+    }
+    let phases = phase_statement
+        .load::<(String, String)>(connection)?
+        .into_iter()
+        .collect::<HashMap<String, String>>();
+    let mut telemetries = telemetry_statement
+        .load::<TelemetryInfo>(connection)?
+        .into_iter()
+        .map(|x| (x.mac.clone(), x))
+        .collect::<HashMap<String, TelemetryInfo>>();
+
+    let server_config = &crate::GLOBAL_CONFIG
+        .get()
+        .expect_or_log("Global config not initialized")
+        .server;
+    let offline_counts = crate::server::presence::count_offline_events(
+        connection,
+        server_config.flap_window_secs,
+        mac,
+    )?
+    .into_iter()
+    .collect::<HashMap<String, i64>>();
+
+    let ips = infos
+        .iter()
+        .filter_map(|x| x.ip.clone())
+        .collect::<Vec<String>>();
+    let seats = seat_dsl::seat
+        .filter(seat_dsl::ip.eq_any(&ips))
+        .load::<SeatInfo>(connection)?
+        .into_iter()
+        .map(|x| (x.ip.clone(), x))
+        .collect::<HashMap<String, SeatInfo>>();
+
+    for info in infos.iter_mut() {
+        if let Some(mac) = &info.mac {
+            info.phase = phases.get(mac).cloned();
+            info.telemetry = telemetries.remove(mac);
+            info.offline_count = offline_counts.get(mac).copied().unwrap_or_default();
+            info.flapping = info.offline_count >= server_config.flap_offline_count;
+        }
+        if let Some(ip) = &info.ip {
+            info.seat = seats.get(ip).cloned();
+        }
+        if let (Some(seat), Some(telemetry)) = (&info.seat, &info.telemetry)
+            && let Some(hostname) = &telemetry.hostname
+        {
+            info.seat_mismatch = !hostname.eq_ignore_ascii_case(&seat.hostname);
+        }
+    }
+    Ok(infos)
 }
 
 #[get("/status")]
@@ -45,11 +253,13 @@ pub async fn get_status(_auth: crate::server::services::Authenticated) -> impl R
     }
 
     let mut response_body = StatusResponse {
+        contest: None,
         bind_count: 0,
         info_count: 0,
         sync_count: 0,
         notsync_count: 0,
         infos: Vec::new(),
+        seats: Vec::new(),
     };
 
     use crate::server::schema::id_bind::dsl as id_bind_dsl;
@@ -66,7 +276,16 @@ pub async fn get_status(_auth: crate::server::services::Authenticated) -> impl R
         }
     }
 
+    match active_contest().first::<String>(&mut connection).optional() {
+        Ok(contest) => response_body.contest = contest,
+        Err(err) => {
+            tracing::error!("Error fetching active contest {}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+
     match player_dsl::player
+        .filter(player_dsl::contest.eq_any(active_contest()))
         .select(count_star())
         .get_result::<i64>(&mut connection)
     {
@@ -78,6 +297,7 @@ pub async fn get_status(_auth: crate::server::services::Authenticated) -> impl R
     }
 
     match player_dsl::player
+        .filter(player_dsl::contest.eq_any(active_contest()))
         .filter(player_dsl::synced.eq(true as i32))
         .count()
         .get_result::<i64>(&mut connection)
@@ -90,6 +310,7 @@ pub async fn get_status(_auth: crate::server::services::Authenticated) -> impl R
     }
 
     match player_dsl::player
+        .filter(player_dsl::contest.eq_any(active_contest()))
         .filter(player_dsl::synced.eq(0))
         .select(count_star())
         .get_result::<i64>(&mut connection)
@@ -101,42 +322,8 @@ pub async fn get_status(_auth: crate::server::services::Authenticated) -> impl R
         }
     }
 
-    let mut infos = match id_bind_dsl::id_bind
-        .left_outer_join(player_dsl::player.on(id_bind_dsl::id.eq(player_dsl::id)))
-        .select((
-            id_bind_dsl::mac.nullable(),
-            id_bind_dsl::id,
-            id_bind_dsl::ip.nullable(),
-            id_bind_dsl::client_version.nullable(),
-            id_bind_dsl::last_seen.nullable(),
-            player_dsl::username.nullable(),
-            player_dsl::password.nullable(),
-            player_dsl::synced.nullable(),
-        ))
-        .load::<(
-            Option<String>,
-            String,
-            Option<String>,
-            Option<String>,
-            Option<String>,
-            Option<String>,
-            Option<String>,
-            Option<i32>,
-        )>(&mut connection)
-    {
-        Ok(result) => result
-            .into_iter()
-            .map(|x| Info {
-                mac: x.0,
-                id: x.1,
-                ip: x.2,
-                client_version: x.3,
-                last_seen: x.4,
-                username: x.5,
-                password: x.6,
-                synced: x.7.map(|i| i % 2 != 0),
-            })
-            .collect::<Vec<Info>>(),
+    let mut infos = match load_bound_infos(&mut connection, None) {
+        Ok(result) => result,
         Err(err) => {
             tracing::error!("Error fetching id_bind LEFT JOIN player: {}", err);
             return HttpResponse::InternalServerError().finish();
@@ -146,6 +333,7 @@ pub async fn get_status(_auth: crate::server::services::Authenticated) -> impl R
     // 第二次查询：player LEFT JOIN id_bind（只拿id_bind没有的数据）
     let extra_infos = match player_dsl::player
         .left_outer_join(id_bind_dsl::id_bind.on(player_dsl::id.eq(id_bind_dsl::id)))
+        .filter(player_dsl::contest.eq_any(active_contest()))
         .filter(id_bind_dsl::id.is_null())
         .select((
             id_bind_dsl::mac.nullable(),
@@ -154,8 +342,12 @@ pub async fn get_status(_auth: crate::server::services::Authenticated) -> impl R
             id_bind_dsl::client_version.nullable(),
             id_bind_dsl::last_seen.nullable(),
             player_dsl::username.nullable(),
-            player_dsl::password.nullable(),
             player_dsl::synced.nullable(),
+            id_bind_dsl::sync_error.nullable(),
+            id_bind_dsl::online.nullable(),
+            player_dsl::team_name.nullable(),
+            player_dsl::organization.nullable(),
+            player_dsl::location.nullable(),
         ))
         .load::<(
             Option<String>,
@@ -164,8 +356,12 @@ pub async fn get_status(_auth: crate::server::services::Authenticated) -> impl R
             Option<String>,
             Option<String>,
             Option<String>,
-            Option<String>,
             Option<i32>,
+            Option<String>,
+            Option<bool>,
+            Option<String>,
+            Option<String>,
+            Option<String>,
         )>(&mut connection)
     {
         Ok(result) => result
@@ -177,8 +373,21 @@ pub async fn get_status(_auth: crate::server::services::Authenticated) -> impl R
                 client_version: x.3,
                 last_seen: x.4,
                 username: x.5,
-                password: x.6,
-                synced: x.7.map(|i| i % 2 != 0),
+                team_name: x.9,
+                organization: x.10,
+                location: x.11,
+                synced: x.6.map(|i| i % 2 != 0),
+                online: x.8,
+                offline_count: 0,
+                flapping: false,
+                sync_error: x.7,
+                phase: None,
+                telemetry: None,
+                seat: None,
+                seat_mismatch: false,
+                fingerprint_mismatch: false,
+                caddy_drift: None,
+                caddy_drift_at: None,
             })
             .collect::<Vec<Info>>(),
         Err(err) => {
@@ -187,6 +396,18 @@ pub async fn get_status(_auth: crate::server::services::Authenticated) -> impl R
         }
     };
 
+    use crate::server::schema::seat::dsl as seat_dsl;
+    match seat_dsl::seat
+        .order((seat_dsl::room, seat_dsl::row, seat_dsl::col))
+        .load::<SeatInfo>(&mut connection)
+    {
+        Ok(seats) => response_body.seats = seats,
+        Err(err) => {
+            tracing::error!("Error fetching seat map {}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+
     infos.extend(extra_infos);
     response_body.infos = infos;
     HttpResponse::Ok().json(response_body)
@@ -0,0 +1,114 @@
+use std::time::Duration;
+
+use actix_web::{HttpResponse, Responder, get, web::Bytes};
+use diesel::SqliteConnection;
+use futures_util::stream;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use super::status::{self, Info};
+
+/// Events buffered for a slow panel before it is told to resync
+const STREAM_CAPACITY: usize = 1024;
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+static STATUS_CHANNEL: Lazy<broadcast::Sender<String>> =
+    Lazy::new(|| broadcast::channel(STREAM_CAPACITY).0);
+
+#[derive(Serialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum StatusEventKind {
+    Bind,
+    Unbind,
+    Report,
+    Sync,
+    Presence,
+    /// Password changed from the panel, a sync is queued
+    Password,
+    /// Events were dropped, the panel should fetch `/status` again
+    Resync,
+}
+
+#[derive(Serialize)]
+struct StatusEvent<'a> {
+    kind: StatusEventKind,
+    mac: Option<&'a str>,
+    /// Row as it appears in `/status`, absent once the bind is removed
+    info: Option<Info>,
+}
+
+/// Push the current row of this MAC to every connected panel
+pub fn publish_status(connection: &mut SqliteConnection, kind: StatusEventKind, mac: &str) {
+    // Nobody is watching, skip the query
+    if STATUS_CHANNEL.receiver_count() == 0 {
+        return;
+    }
+
+    let info = match kind {
+        StatusEventKind::Unbind => None,
+        _ => match status::load_bound_infos(connection, Some(mac)) {
+            Ok(infos) => infos.into_iter().next(),
+            Err(err) => {
+                tracing::error!(
+                    "Error loading status of MAC {} for stream, err {}",
+                    mac,
+                    err
+                );
+                return;
+            }
+        },
+    };
+    let event = StatusEvent {
+        kind,
+        mac: Some(mac),
+        info,
+    };
+    match serde_json::to_string(&event) {
+        Ok(data) => {
+            // Only fails when the last panel disconnected in the meantime
+            let _ = STATUS_CHANNEL.send(data);
+        }
+        Err(err) => tracing::error!("Error serializing status event, err {}", err),
+    }
+}
+
+fn sse_data(data: &str) -> Bytes {
+    Bytes::from(format!("data: {data}\n\n"))
+}
+
+#[get("/status/stream")]
+pub async fn get_status_stream(_auth: crate::server::services::Authenticated) -> impl Responder {
+    let receiver = STATUS_CHANNEL.subscribe();
+    let mut keepalive = actix_web::rt::time::interval(KEEPALIVE_INTERVAL);
+    keepalive.reset();
+
+    let body = stream::unfold(
+        (receiver, keepalive),
+        |(mut receiver, mut keepalive)| async move {
+            let chunk = tokio::select! {
+                message = receiver.recv() => match message {
+                    Ok(data) => sse_data(&data),
+                    Err(RecvError::Lagged(skipped)) => {
+                        tracing::warn!("Status stream lagged, {} event(s) dropped", skipped);
+                        let event = StatusEvent {
+                            kind: StatusEventKind::Resync,
+                            mac: None,
+                            info: None,
+                        };
+                        sse_data(&serde_json::to_string(&event).unwrap_or_default())
+                    }
+                    Err(RecvError::Closed) => return None,
+                },
+                // Comment lines keep proxies from closing an idle connection
+                _ = keepalive.tick() => Bytes::from_static(b": keepalive\n\n"),
+            };
+            Some((Ok::<_, actix_web::Error>(chunk), (receiver, keepalive)))
+        },
+    );
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .streaming(body)
+}
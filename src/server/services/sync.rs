@@ -11,6 +11,8 @@ use tracing_unwrap::OptionExt;
 #[derive(Deserialize)]
 struct SyncRequestBody {
     mac: String,
+    #[serde(default)]
+    fingerprint: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -43,21 +45,17 @@ impl FromRequest for Authenticated {
 }
 
 #[post("/sync")]
-pub async fn sync_info(_auth: Authenticated, body: Json<SyncRequestBody>) -> impl Responder {
-    let sync_enabled = crate::GLOBAL_CONFIG
-        .get()
-        .expect_or_log("Global config not initialized!")
-        .server
-        .enable_sync;
-
-    if !sync_enabled {
-        tracing::warn!(
-            "MAC {} try to sync info with sync service disabled!",
-            body.mac
-        );
-        return HttpResponse::Forbidden()
-            .body("Bind is not enabled! This request has been logged".to_string());
-    }
+pub async fn sync_info(
+    req: HttpRequest,
+    _auth: Authenticated,
+    identity: super::ClientIdentity,
+    body: Json<SyncRequestBody>,
+) -> impl Responder {
+    let mac = identity.resolve(&body.mac);
+    let actor = super::AuditActor::client(
+        Some(mac.clone()),
+        req.peer_addr().map(|addr| addr.ip().to_string()),
+    );
     let connection_pool = crate::server::database::DB_CONNECTION_POOL
         .get()
         .unwrap_or_log();
@@ -73,11 +71,26 @@ pub async fn sync_info(_auth: Authenticated, body: Json<SyncRequestBody>) -> imp
         }
     }
 
+    let sync_enabled = crate::server::toggle::Toggle::Sync.enabled();
+
+    if !sync_enabled {
+        tracing::warn!("MAC {} try to sync info with sync service disabled!", mac);
+        super::record_audit(
+            &mut connection,
+            super::AuditEvent::SyncDisabled,
+            &actor,
+            None,
+            "Sync service disabled",
+        );
+        return HttpResponse::Forbidden()
+            .body("Bind is not enabled! This request has been logged".to_string());
+    }
+
     use crate::server::schema::id_bind::dsl as id_bind_dsl;
     use crate::server::schema::player::dsl as player_dsl;
     let id;
     match id_bind_dsl::id_bind
-        .filter(id_bind_dsl::mac.eq(&body.mac))
+        .filter(id_bind_dsl::mac.eq(&mac))
         .select(id_bind_dsl::id)
         .first::<String>(&mut connection)
         .optional()
@@ -85,6 +98,13 @@ pub async fn sync_info(_auth: Authenticated, body: Json<SyncRequestBody>) -> imp
         Ok(result) => match result {
             Some(result) => id = result,
             None => {
+                super::record_audit(
+                    &mut connection,
+                    super::AuditEvent::SyncDenied,
+                    &actor,
+                    None,
+                    "No ID bound to this MAC",
+                );
                 return HttpResponse::Forbidden().body("No ID bind to this MAC!");
             }
         },
@@ -94,7 +114,20 @@ pub async fn sync_info(_auth: Authenticated, body: Json<SyncRequestBody>) -> imp
         }
     }
 
+    // Only flagged for staff, the client certificate already authenticates the machine
+    if let Err(err) =
+        super::bind::check_fingerprint(&mut connection, &mac, body.fingerprint.as_deref(), &actor)
+    {
+        tracing::error!(
+            "Error checking machine fingerprint of MAC {}, err {}",
+            mac,
+            err
+        );
+        return HttpResponse::InternalServerError().finish();
+    }
+
     let response: SyncResponseBody = match player_dsl::player
+        .filter(player_dsl::contest.eq_any(crate::server::contest::active_contest()))
         .filter(player_dsl::id.eq(&id))
         .select((
             player_dsl::username,
@@ -102,13 +135,42 @@ pub async fn sync_info(_auth: Authenticated, body: Json<SyncRequestBody>) -> imp
             player_dsl::synced,
         ))
         .first::<(String, String, i32)>(&mut connection)
+        .optional()
     {
-        Ok((username, password, _)) => SyncResponseBody { username, password },
+        Ok(Some((username, password, _))) => {
+            // The only place serving the password in clear to a client
+            match crate::server::credential::decrypt_password(&password) {
+                Ok(password) => SyncResponseBody { username, password },
+                Err(err) => {
+                    tracing::error!("Failed to decrypt password of ID {}, err {:#}", id, err);
+                    return HttpResponse::InternalServerError().finish();
+                }
+            }
+        }
+        Ok(None) => {
+            tracing::warn!("ID {} of MAC {} missing in active contest", id, mac);
+            super::record_audit(
+                &mut connection,
+                super::AuditEvent::SyncDenied,
+                &actor,
+                Some(&id),
+                "ID not in the active contest",
+            );
+            return HttpResponse::Forbidden().body("ID not in the active contest!");
+        }
         Err(err) => {
             tracing::error!("Failed to get info by ID from database, err: {}", err);
             return HttpResponse::InternalServerError().finish();
         }
     };
-    tracing::info!("Synced MAC {} with user {}", body.mac, response.username);
+    tracing::info!("Synced MAC {} with user {}", mac, response.username);
+    super::record_audit(
+        &mut connection,
+        super::AuditEvent::Sync,
+        &actor,
+        Some(&id),
+        &format!("Credentials of user {} served", response.username),
+    );
+    super::publish_status(&mut connection, super::StatusEventKind::Sync, &mac);
     HttpResponse::Ok().json(response)
 }
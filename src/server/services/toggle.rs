@@ -0,0 +1,77 @@
+use std::collections::BTreeMap;
+
+use actix_web::{HttpResponse, Responder, get, post, web::Json};
+use tracing_unwrap::OptionExt;
+
+use crate::server::toggle::{Toggle, set_toggle};
+
+/// Current value of every toggle keyed by its config name
+fn toggle_states() -> BTreeMap<&'static str, bool> {
+    Toggle::ALL
+        .into_iter()
+        .map(|toggle| (toggle.as_str(), toggle.enabled()))
+        .collect()
+}
+
+#[get("/toggle")]
+pub async fn get_toggles(_auth: crate::server::services::Authenticated) -> impl Responder {
+    HttpResponse::Ok().json(toggle_states())
+}
+
+/// Change any subset of toggles, e.g. `{"enable_bind": false}`, unknown names are refused
+#[post("/toggle")]
+pub async fn set_toggles(
+    auth: crate::server::services::Authenticated,
+    body: Json<BTreeMap<String, bool>>,
+) -> impl Responder {
+    let mut changes = Vec::new();
+    for (name, enabled) in body.iter() {
+        match Toggle::parse(name) {
+            Some(toggle) => changes.push((toggle, *enabled)),
+            None => return HttpResponse::BadRequest().body(format!("Unknown toggle {}", name)),
+        }
+    }
+
+    let connection_pool = crate::server::database::DB_CONNECTION_POOL
+        .get()
+        .unwrap_or_log();
+
+    let mut connection;
+    match connection_pool.get() {
+        Ok(conn) => {
+            connection = conn;
+        }
+        Err(err) => {
+            tracing::error!("Error getting database connection {}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+
+    for (toggle, enabled) in changes {
+        match set_toggle(&mut connection, toggle, enabled) {
+            Ok(previous) => {
+                if previous == enabled {
+                    continue;
+                }
+                tracing::info!("Runtime toggle {} set to {}", toggle.as_str(), enabled);
+                super::record_audit(
+                    &mut connection,
+                    super::AuditEvent::ToggleChanged,
+                    &auth.actor(),
+                    Some(toggle.as_str()),
+                    &format!("{} -> {}", previous, enabled),
+                );
+            }
+            Err(err) => {
+                tracing::error!(
+                    "Error setting runtime toggle {}, err {}",
+                    toggle.as_str(),
+                    err
+                );
+                return HttpResponse::InternalServerError().finish();
+            }
+        }
+    }
+
+    HttpResponse::Ok().json(toggle_states())
+}
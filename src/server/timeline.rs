@@ -0,0 +1,148 @@
+use std::{collections::HashSet, time::Duration};
+
+use anyhow::bail;
+use chrono::{DateTime, Utc};
+use diesel::{
+    dsl::{exists, insert_into, select},
+    prelude::*,
+};
+use tracing_unwrap::OptionExt;
+
+use super::schema::id_bind::dsl as id_bind_dsl;
+use super::schema::timeline_step::dsl as timeline_dsl;
+use super::services::{RemoteCommand, enqueue_commands};
+use crate::config::TimelineStep;
+
+pub const STEP_FIRED: &str = "fired";
+pub const STEP_MISSED: &str = "missed";
+
+pub struct ScheduledStep {
+    name: String,
+    at: DateTime<Utc>,
+    command: RemoteCommand,
+    ids: Vec<String>,
+}
+
+/// Validate the configured timeline so a typo fails at startup instead of at contest time
+pub fn parse_timeline(steps: &[TimelineStep]) -> anyhow::Result<Vec<ScheduledStep>> {
+    let mut names = HashSet::new();
+    let mut scheduled = Vec::new();
+    for step in steps {
+        if !names.insert(step.name.as_str()) {
+            bail!("Duplicate timeline step name {}", step.name);
+        }
+        let at = match DateTime::parse_from_rfc3339(&step.at) {
+            Ok(at) => at.with_timezone(&Utc),
+            Err(err) => bail!(
+                "Invalid time {} for timeline step {}: {err}",
+                step.at,
+                step.name
+            ),
+        };
+        let Some(command) = RemoteCommand::parse(&step.command) else {
+            bail!(
+                "Unknown command {} for timeline step {}",
+                step.command,
+                step.name
+            );
+        };
+        scheduled.push(ScheduledStep {
+            name: step.name.clone(),
+            at,
+            command,
+            ids: step.ids.clone(),
+        });
+    }
+    scheduled.sort_by_key(|step| step.at);
+    Ok(scheduled)
+}
+
+/// Returns whether the step is finished, either fired now, fired before, or missed
+fn fire_step(step: &ScheduledStep, grace_secs: i64) -> anyhow::Result<bool> {
+    let now = Utc::now();
+    if now < step.at {
+        return Ok(false);
+    }
+
+    let connection_pool = super::database::DB_CONNECTION_POOL.get().unwrap_or_log();
+    let mut connection = connection_pool.get()?;
+
+    connection.transaction::<_, anyhow::Error, _>(|connection| {
+        let executed = select(exists(
+            timeline_dsl::timeline_step.filter(timeline_dsl::name.eq(&step.name)),
+        ))
+        .get_result::<bool>(connection)?;
+        if executed {
+            return Ok(true);
+        }
+
+        let overdue = now.signed_duration_since(step.at).num_seconds();
+        let (status, target_count) = if overdue > grace_secs {
+            tracing::warn!(
+                "Timeline step {} overdue by {}s, marking as missed",
+                step.name,
+                overdue
+            );
+            (STEP_MISSED, 0)
+        } else {
+            let mut query = id_bind_dsl::id_bind
+                .filter(id_bind_dsl::id.ne("UNKNOWN"))
+                .select(id_bind_dsl::mac)
+                .into_boxed();
+            if !step.ids.is_empty() {
+                query = query.filter(id_bind_dsl::id.eq_any(&step.ids));
+            }
+            let macs = query.load::<String>(connection)?;
+            let count = enqueue_commands(connection, &macs, step.command, Some(&step.name))?;
+            tracing::info!(
+                "Timeline step {} fired command {} for {} seat(s)",
+                step.name,
+                step.command.as_str(),
+                count
+            );
+            (STEP_FIRED, count as i32)
+        };
+
+        insert_into(timeline_dsl::timeline_step)
+            .values((
+                timeline_dsl::name.eq(&step.name),
+                timeline_dsl::command.eq(step.command.as_str()),
+                timeline_dsl::status.eq(status),
+                timeline_dsl::target_count.eq(target_count),
+                timeline_dsl::executed_at.eq(now.timestamp().to_string()),
+            ))
+            .execute(connection)?;
+        Ok(true)
+    })
+}
+
+pub async fn run_timeline(steps: Vec<ScheduledStep>, grace_secs: i64) {
+    if steps.is_empty() {
+        return;
+    }
+    tracing::info!("Contest timeline scheduled with {} step(s)", steps.len());
+
+    let mut finished = HashSet::new();
+    let mut interval = actix_web::rt::time::interval(Duration::from_secs(5));
+    loop {
+        interval.tick().await;
+        for step in &steps {
+            if finished.contains(&step.name) {
+                continue;
+            }
+            match fire_step(step, grace_secs) {
+                Ok(true) => {
+                    finished.insert(step.name.clone());
+                }
+                Ok(false) => {}
+                Err(err) => {
+                    tracing::error!("Error firing timeline step {}, err {:#}", step.name, err);
+                }
+            }
+        }
+        if finished.len() == steps.len() {
+            tracing::info!("All timeline steps finished");
+            return;
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use chrono::Utc;
+use diesel::{
+    dsl::{insert_or_ignore_into, update},
+    prelude::*,
+};
+
+use super::schema::runtime_toggle::dsl as toggle_dsl;
+use crate::config::ServerConfig;
+
+/// Live value of every toggle, loaded from the database on start and written through on change
+static TOGGLES: [AtomicBool; Toggle::ALL.len()] =
+    [const { AtomicBool::new(false) }; Toggle::ALL.len()];
+
+/// Service switches flipped at runtime from the panel, the server config only seeds them
+#[derive(Clone, Copy, Debug)]
+pub enum Toggle {
+    Bind,
+    BindUpdate,
+    Sync,
+    StaticFile,
+}
+
+impl Toggle {
+    pub const ALL: [Toggle; 4] = [
+        Toggle::Bind,
+        Toggle::BindUpdate,
+        Toggle::Sync,
+        Toggle::StaticFile,
+    ];
+
+    /// Same as the server config key seeding it
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Toggle::Bind => "enable_bind",
+            Toggle::BindUpdate => "enable_bind_update",
+            Toggle::Sync => "enable_sync",
+            Toggle::StaticFile => "enable_static_file",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Toggle::ALL
+            .into_iter()
+            .find(|toggle| toggle.as_str() == name)
+    }
+
+    fn config_default(&self, config: &ServerConfig) -> bool {
+        match self {
+            Toggle::Bind => config.enable_bind,
+            Toggle::BindUpdate => config.enable_bind_update,
+            Toggle::Sync => config.enable_sync,
+            Toggle::StaticFile => config.enable_static_file,
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        TOGGLES[*self as usize].load(Ordering::Relaxed)
+    }
+}
+
+/// Seed toggles missing in the database from the server config, then load the stored values.
+/// Once stored, a toggle keeps its value across restarts regardless of the config.
+pub fn init_toggles(connection: &mut SqliteConnection, config: &ServerConfig) -> QueryResult<()> {
+    for toggle in Toggle::ALL {
+        insert_or_ignore_into(toggle_dsl::runtime_toggle)
+            .values((
+                toggle_dsl::name.eq(toggle.as_str()),
+                toggle_dsl::enabled.eq(toggle.config_default(config)),
+            ))
+            .execute(connection)?;
+    }
+
+    let stored = toggle_dsl::runtime_toggle
+        .select((toggle_dsl::name, toggle_dsl::enabled))
+        .load::<(String, bool)>(connection)?;
+    for (name, enabled) in stored {
+        let Some(toggle) = Toggle::parse(&name) else {
+            tracing::warn!("Ignoring unknown runtime toggle {}", name);
+            continue;
+        };
+        if enabled != toggle.config_default(config) {
+            tracing::info!(
+                "Runtime toggle {} is {}, overriding the server config",
+                name,
+                enabled
+            );
+        }
+        TOGGLES[toggle as usize].store(enabled, Ordering::Relaxed);
+    }
+    Ok(())
+}
+
+/// Persist a toggle and apply it to the running server, returning the previous value
+pub fn set_toggle(
+    connection: &mut SqliteConnection,
+    toggle: Toggle,
+    enabled: bool,
+) -> QueryResult<bool> {
+    update(toggle_dsl::runtime_toggle.find(toggle.as_str()))
+        .set((
+            toggle_dsl::enabled.eq(enabled),
+            toggle_dsl::updated_at.eq(Utc::now().timestamp().to_string()),
+        ))
+        .execute(connection)?;
+    Ok(TOGGLES[toggle as usize].swap(enabled, Ordering::Relaxed))
+}